@@ -0,0 +1,237 @@
+use crate::board::*;
+use crate::eval::*;
+use crate::piece::*;
+use crate::pos::*;
+
+// Ends games early once their result is clear, so weak or slow controllers don't play out lost positions.
+// Scores are from the reference evaluator, in centipawns, and counted in plies like the rest of the game loop.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdjudicationReason {
+    // The evaluation was decisive for too long
+    Resign,
+    // The evaluation stayed close to zero late in the game
+    Draw,
+    // One side was too far ahead in material
+    Material,
+}
+
+impl AdjudicationReason {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AdjudicationReason::Resign => "resign adjudication",
+            AdjudicationReason::Draw => "draw adjudication",
+            AdjudicationReason::Material => "material adjudication",
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResignRule {
+    pub score: i64,
+    pub plies: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrawRule {
+    // Only applies once this many plies have been played
+    pub after: usize,
+    pub score: i64,
+    pub plies: usize,
+}
+
+// Difference in Piece::score units, kings excluded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaterialRule {
+    pub difference: i64,
+    pub plies: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Adjudication {
+    pub resign: Option<ResignRule>,
+    pub draw: Option<DrawRule>,
+    pub material: Option<MaterialRule>,
+}
+
+impl Adjudication {
+    // Games are played out
+    pub fn new() -> Adjudication {
+        Adjudication::default()
+    }
+
+    pub fn standard() -> Adjudication {
+        Adjudication {
+            resign: Some(ResignRule { score: 1000, plies: 8 }),
+            draw: Some(DrawRule { after: 60, score: 20, plies: 10 }),
+            material: Some(MaterialRule { difference: 15, plies: 6 }),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.resign.is_some() || self.draw.is_some() || self.material.is_some()
+    }
+}
+
+
+// Winner, None for a draw
+pub type Adjudicated = (Option<Color>, AdjudicationReason);
+
+// Follows a single game
+pub struct Adjudicator {
+    rules: Adjudication,
+    evaluator: Evaluator,
+    // Consecutive plies with white ahead (positive) or behind (negative)
+    resign_streak: i64,
+    draw_streak: usize,
+    material_streak: i64,
+}
+
+impl Adjudicator {
+    pub fn new(rules: Adjudication) -> Adjudicator {
+        Adjudicator {
+            rules,
+            evaluator: Evaluator::new(),
+            resign_streak: 0,
+            draw_streak: 0,
+            material_streak: 0,
+        }
+    }
+
+    // Called after every ply with the number of plies played so far
+    pub fn update(&mut self, board: &Board, plies: usize) -> Option<Adjudicated> {
+        if self.rules.resign.is_some() || self.rules.draw.is_some() {
+            let score = self.evaluator.evaluate(board, Color::White);
+
+            if let Some(rule) = self.rules.resign {
+                self.resign_streak = streak(self.resign_streak, score, rule.score);
+                if self.resign_streak.unsigned_abs() as usize >= rule.plies {
+                    return Some((Some(leader(self.resign_streak)), AdjudicationReason::Resign));
+                }
+            }
+
+            if let Some(rule) = self.rules.draw {
+                if plies >= rule.after && score.abs() <= rule.score {
+                    self.draw_streak += 1;
+                } else {
+                    self.draw_streak = 0;
+                }
+                if self.draw_streak >= rule.plies {
+                    return Some((None, AdjudicationReason::Draw));
+                }
+            }
+        }
+
+        if let Some(rule) = self.rules.material {
+            self.material_streak = streak(self.material_streak, material_difference(board), rule.difference);
+            if self.material_streak.unsigned_abs() as usize >= rule.plies {
+                return Some((Some(leader(self.material_streak)), AdjudicationReason::Material));
+            }
+        }
+
+        None
+    }
+}
+
+// Extends the streak of the side ahead by at least threshold, or restarts it
+fn streak(current: i64, score: i64, threshold: i64) -> i64 {
+    if score >= threshold {
+        current.max(0) + 1
+    } else if score <= -threshold {
+        current.min(0) - 1
+    } else {
+        0
+    }
+}
+
+fn leader(streak: i64) -> Color {
+    if streak > 0 { Color::White } else { Color::Black }
+}
+
+// White's material minus black's
+pub fn material_difference(board: &Board) -> i64 {
+    let mut difference = 0;
+    for i in 0..64 {
+        if let Some(p) = board.piece_at(Pos::from_index(i)) {
+            if p.piece == Piece::King {
+                continue;
+            }
+            match p.color {
+                Color::White => difference += p.piece.score(),
+                Color::Black => difference -= p.piece.score(),
+            }
+        }
+    }
+    difference
+}
+
+
+
+#[test]
+fn adjudication_material_needs_consecutive_plies() {
+    let mut board = Board::new();
+    let rules = Adjudication {
+        material: Some(MaterialRule { difference: 10, plies: 3 }),
+        ..Adjudication::new()
+    };
+    let mut adjudicator = Adjudicator::new(rules);
+    assert_eq!(adjudicator.update(&board, 1), None);
+
+    // Black is missing its queen
+    board.clear_piece(Pos::new(4, 7));
+    assert_eq!(material_difference(&board), 10);
+    assert_eq!(adjudicator.update(&board, 2), None);
+    assert_eq!(adjudicator.update(&board, 3), None);
+    assert_eq!(adjudicator.update(&Board::new(), 4), None);
+    assert_eq!(adjudicator.update(&board, 5), None);
+    assert_eq!(adjudicator.update(&board, 6), None);
+    assert_eq!(adjudicator.update(&board, 7), Some((Some(Color::White), AdjudicationReason::Material)));
+}
+
+#[test]
+fn adjudication_draw_only_late() {
+    let rules = Adjudication {
+        draw: Some(DrawRule { after: 10, score: 50, plies: 2 }),
+        ..Adjudication::new()
+    };
+    let mut adjudicator = Adjudicator::new(rules);
+    // Bare kings, so the evaluation is even
+    let mut board = Board::empty();
+    board.set_piece(Pos::new(4, 0), Piece::King.colored(Color::White));
+    board.set_piece(Pos::new(4, 7), Piece::King.colored(Color::Black));
+    for plies in 0..10 {
+        assert_eq!(adjudicator.update(&board, plies), None);
+    }
+    assert_eq!(adjudicator.update(&board, 10), None);
+    assert_eq!(adjudicator.update(&board, 11), Some((None, AdjudicationReason::Draw)));
+}
+
+#[test]
+fn adjudication_after_king_capture() {
+    use crate::ai::*;
+    use crate::elo::*;
+    use crate::opening::*;
+
+    // Black is far ahead but its king hangs, and the capture would complete the material streak
+    let mut board = Board::empty();
+    board.set_piece(Pos::new(0, 0), Piece::King.colored(Color::White));
+    board.set_piece(Pos::new(3, 0), Piece::Queen.colored(Color::White));
+    board.set_piece(Pos::new(3, 7), Piece::King.colored(Color::Black));
+    board.set_piece(Pos::new(6, 6), Piece::Queen.colored(Color::Black));
+    board.set_piece(Pos::new(7, 6), Piece::Rook.colored(Color::Black));
+    board.set_piece(Pos::new(7, 5), Piece::Rook.colored(Color::Black));
+    let opening = Opening::new("hanging king", board, Color::White);
+
+    let settings = GameSettings {
+        adjudication: Adjudication {
+            material: Some(MaterialRule { difference: 5, plies: 1 }),
+            ..Adjudication::new()
+        },
+        ..GameSettings::new()
+    };
+    let (white, black) = (RandomAI::new_with_seed(true, 1), FirstMoveAI::new());
+    let outcome = play_opening([&white, &black], &settings, 1, 0, &opening);
+    assert_eq!(outcome.termination, Termination::KingCaptured);
+    assert_eq!(outcome.winner, Some(0));
+}
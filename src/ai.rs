@@ -3,10 +3,14 @@ use crate::piece::*;
 use crate::pos::*;
 use crate::moves::*;
 use crate::player::*;
+use crate::clock::*;
+use crate::elo::*;
 
 use std::cmp;
+use std::cell::RefCell;
 
-use rand::{thread_rng, Rng};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
 
 
 
@@ -27,13 +31,15 @@ impl PlayerController for FirstMoveAI {
         "FirstMove".to_string()
     }
 
-    fn play(&self, color: Color, board: &Board) -> Option<Move> {
+    // The inner loop only looks at the first move of each piece
+    #[allow(clippy::never_loop)]
+    fn play(&self, color: Color, board: &Board) -> Action {
         for src in board.pieces(color).iter() {
             for dst in possible_moves(board, src).iter() {
-                return Some(Move(src, dst));
+                return Action::Move(Move(src, dst));
             }
         }
-        None
+        Action::Resign
     }
 }
 
@@ -41,13 +47,22 @@ impl PlayerController for FirstMoveAI {
 
 
 pub struct RandomAI {
-    check_mat: bool
+    check_mat: bool,
+    rng: RefCell<StdRng>,
 }
 
 impl RandomAI {
     pub fn new(check_mat: bool) -> RandomAI {
         RandomAI {
-            check_mat: check_mat
+            check_mat: check_mat,
+            rng: RefCell::new(StdRng::from_entropy()),
+        }
+    }
+
+    pub fn new_with_seed(check_mat: bool, seed: u64) -> RandomAI {
+        RandomAI {
+            check_mat,
+            rng: RefCell::new(StdRng::seed_from_u64(seed)),
         }
     }
 }
@@ -57,26 +72,27 @@ impl PlayerController for RandomAI {
         "Random".to_string()
     }
 
-    fn play(&self, color: Color, board: &Board) -> Option<Move> {
+    fn play(&self, color: Color, board: &Board) -> Action {
         let moves = board.possible_moves(color).collect::<Vec<_>>();
 
         if self.check_mat {
             let enemy_king = board.king_pos(color.inverse()).unwrap_or(Pos::from_index(0));
             for m in &moves {
                 if m.1 == enemy_king {
-                    return Some(*m);
+                    return Action::Move(*m);
                 }
             }
         }
 
         match moves.len() {
-            0 => None,
-            l => {
-                let mut rng = thread_rng();
-                Some(moves[rng.gen_range(0, l)])
-            }
+            0 => Action::Resign,
+            l => Action::Move(moves[self.rng.borrow_mut().gen_range(0, l)])
         }
     }
+
+    fn reseed(&self, seed: u64) {
+        *self.rng.borrow_mut() = StdRng::seed_from_u64(seed);
+    }
 }
 
 
@@ -84,7 +100,9 @@ impl PlayerController for RandomAI {
 
 
 pub struct CaptureAI {
-    fallback: Box<dyn PlayerController>
+    fallback: Box<dyn PlayerController>,
+    // Only take captures that do not lose material according to static exchange evaluation
+    safe: bool,
 }
 
 impl CaptureAI {
@@ -95,17 +113,24 @@ impl CaptureAI {
 
     pub fn new_with_fallback<T: 'static + PlayerController>(fallback: T) -> CaptureAI {
         CaptureAI {
-            fallback: Box::new(fallback)
+            fallback: Box::new(fallback),
+            safe: false,
         }
     }
-}
 
-impl PlayerController for CaptureAI {
-    fn name(&self) -> String {
-        format!("{}Capture", self.fallback.name())
+    pub fn new_safe() -> CaptureAI {
+        let fallback = SwarmAI::new();
+        CaptureAI::new_safe_with_fallback(fallback)
+    }
+
+    pub fn new_safe_with_fallback<T: 'static + PlayerController>(fallback: T) -> CaptureAI {
+        CaptureAI {
+            fallback: Box::new(fallback),
+            safe: true,
+        }
     }
 
-    fn play(&self, color: Color, board: &Board) -> Option<Move> {
+    fn best_capture(&self, color: Color, board: &Board) -> Option<Move> {
         let mut capture_score = -1;
         let mut best_capture = None;
 
@@ -115,6 +140,9 @@ impl PlayerController for CaptureAI {
             for dst in possible_moves(board, src).iter() {
                 if let Some(p) = board.piece_at(dst) {
                     if p.color == enemy_color {
+                        if self.safe && board.see(Move(src, dst)) < 0 {
+                            continue;
+                        }
                         let score = p.piece.score();
                         if capture_score < score {
                             best_capture = Some(Move(src, dst));
@@ -124,12 +152,53 @@ impl PlayerController for CaptureAI {
                 }
             }
         }
+        best_capture
+    }
+}
+
+impl PlayerController for CaptureAI {
+    fn name(&self) -> String {
+        if self.safe {
+            format!("{}SafeCapture", self.fallback.name())
+        } else {
+            format!("{}Capture", self.fallback.name())
+        }
+    }
 
-        match best_capture {
+    fn play(&self, color: Color, board: &Board) -> Action {
+        match self.best_capture(color, board) {
             None => self.fallback.play(color, board),
-            m => m
+            Some(m) => Action::Move(m)
         }
     }
+
+    fn play_with_clock(&self, color: Color, board: &Board, clock: &ClockInfo) -> Action {
+        match self.best_capture(color, board) {
+            None => self.fallback.play_with_clock(color, board, clock),
+            Some(m) => Action::Move(m)
+        }
+    }
+
+    // Everything else is the fallback's, it has to follow the game even when captures are played instead of its moves
+    fn respond_to_draw(&self, color: Color, board: &Board) -> Action {
+        self.fallback.respond_to_draw(color, board)
+    }
+
+    fn reseed(&self, seed: u64) {
+        self.fallback.reseed(seed);
+    }
+
+    fn new_game(&self, color: Color, board: &Board) {
+        self.fallback.new_game(color, board);
+    }
+
+    fn opponent_moved(&self, m: Move, board: &Board) {
+        self.fallback.opponent_moved(m, board);
+    }
+
+    fn game_over(&self, result: PlayerResult, termination: Termination) {
+        self.fallback.game_over(result, termination);
+    }
 }
 
 
@@ -150,7 +219,7 @@ impl PlayerController for SwarmAI {
         "Swarm".to_string()
     }
 
-    fn play(&self, color: Color, board: &Board) -> Option<Move> {
+    fn play(&self, color: Color, board: &Board) -> Action {
         let mut best_score = 8 + 8 + 1;
         let mut best_move = None;
 
@@ -166,7 +235,7 @@ impl PlayerController for SwarmAI {
             }
         }
         
-        best_move
+        best_move.into()
     }
 }
 
@@ -180,4 +249,57 @@ fn rook_distance(a: Pos, b: Pos) -> i64 {
     let d_col = (a.col() as i64) - (b.col() as i64);
     let d_row = (a.row() as i64) - (b.row() as i64);
     d_col.abs() + d_row.abs()
-}
\ No newline at end of file
+}
+
+
+#[test]
+fn wrappers_forward_hooks() {
+    use crate::mcts::*;
+    use std::rc::Rc;
+
+    // Shares what it was told with the test once it is owned by a wrapper
+    struct Recorder {
+        ai: FirstMoveAI,
+        events: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl PlayerController for Recorder {
+        fn name(&self) -> String {
+            "Recorder".to_string()
+        }
+
+        fn play(&self, color: Color, board: &Board) -> Action {
+            self.ai.play(color, board)
+        }
+
+        fn play_with_clock(&self, color: Color, board: &Board, _clock: &ClockInfo) -> Action {
+            self.events.borrow_mut().push("clock".to_string());
+            self.ai.play(color, board)
+        }
+
+        fn new_game(&self, color: Color, _board: &Board) {
+            self.events.borrow_mut().push(format!("new {}", color));
+        }
+
+        fn opponent_moved(&self, _m: Move, _board: &Board) {
+            self.events.borrow_mut().push("moved".to_string());
+        }
+
+        fn game_over(&self, result: PlayerResult, _termination: Termination) {
+            self.events.borrow_mut().push(format!("{:?}", result));
+        }
+    }
+
+    // No capture is possible in the first moves, so the fallback plays them
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let capture = CaptureAI::new_with_fallback(Recorder { ai: FirstMoveAI::new(), events: events.clone() });
+    let settings = GameSettings { max_moves: 4, time_control: Some(TimeControl::SuddenDeath(std::time::Duration::from_secs(60))), ..GameSettings::new() };
+    play_once_with_white([&capture, &FirstMoveAI::new()], &settings, 3, 1);
+    assert_eq!(*events.borrow(), ["new Black", "moved", "clock", "moved", "Draw"]);
+
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let mcts = MctsAI::new_with_rollout(Budget::Iterations(10), Recorder { ai: FirstMoveAI::new(), events: events.clone() });
+    let settings = GameSettings { max_moves: 2, ..GameSettings::new() };
+    play_once_with_white([&mcts, &FirstMoveAI::new()], &settings, 3, 0);
+    assert_eq!(*events.borrow(), ["new White", "Draw"]);
+}
@@ -0,0 +1,59 @@
+// Scripted stand-in for a UCI engine, used to test the engine adapter without a real engine.
+// It doesn't know the rules: it answers every go with the next move of its script, given as arguments.
+//
+//   uci_stub [--name <name>] [--hang] [--crash-after <n>] <move>...
+//
+// --hang never answers go (not even stop), --crash-after exits on the n-th go.
+
+use std::io::{self, BufRead, Write};
+use std::process;
+
+fn main() {
+    let mut name = "UciStub".to_string();
+    let mut hang = false;
+    let mut crash_after = None;
+    let mut script = Vec::new();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--name" => name = args.next().expect("Missing name"),
+            "--hang" => hang = true,
+            "--crash-after" => crash_after = Some(args.next().and_then(|n| n.parse::<usize>().ok()).expect("Invalid go count")),
+            _ => script.push(arg),
+        }
+    }
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut searches = 0;
+    for line in io::stdin().lock().lines() {
+        let line = line.unwrap_or_default();
+        let command = line.split_whitespace().next().unwrap_or("");
+        match command {
+            "uci" => {
+                writeln!(out, "id name {}", name).unwrap();
+                writeln!(out, "id author nobody").unwrap();
+                writeln!(out, "uciok").unwrap();
+            }
+            "isready" => writeln!(out, "readyok").unwrap(),
+            "go" => {
+                searches += 1;
+                if crash_after == Some(searches) {
+                    process::exit(1);
+                }
+                if hang {
+                    continue;
+                }
+                let m = if script.is_empty() { "0000" } else { &script[(searches - 1) % script.len()] };
+                writeln!(out, "info string scripted").unwrap();
+                writeln!(out, "info depth 1 seldepth 1 score cp {} nodes {} time 0 pv {}", 10 * searches, searches, m).unwrap();
+                writeln!(out, "bestmove {}", m).unwrap();
+            }
+            "quit" => break,
+            // position, ucinewgame, setoption and stop need no answer
+            _ => {}
+        }
+        out.flush().unwrap();
+    }
+}
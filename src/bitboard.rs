@@ -14,6 +14,22 @@ impl BitBoard {
         }
     }
     
+    pub fn from_raw(board: u64) -> Self {
+        BitBoard {
+            board
+        }
+    }
+
+    #[inline(always)]
+    pub fn raw(&self) -> u64 {
+        self.board
+    }
+
+    #[inline(always)]
+    pub fn count(&self) -> usize {
+        self.board.count_ones() as usize
+    }
+    
     #[inline(always)]
     pub fn is_empty(&self) -> bool {
         self.board == 0
@@ -65,6 +81,13 @@ impl BitBoard {
     }
 
 
+    pub fn without_board(&self, board: BitBoard) -> BitBoard {
+        BitBoard {
+            board: self.board & !board.board
+        }
+    }
+
+
     
     pub fn add_row(&mut self, row: usize) {
         self.board = self.with_row(row).board;
@@ -2,30 +2,38 @@ use crate::bitboard::*;
 use crate::piece::*;
 use crate::moves::*;
 use crate::pos::*;
+use crate::zobrist;
 
 use std::fmt;
+use std::cmp;
 
 #[derive(Clone)]
 pub struct Board {
-    board: [ColoredPiece; 64]
+    board: [ColoredPiece; 64],
+
+    // Zobrist keys of the whole board and of the pawns only, kept up to date by set_piece
+    hash: u64,
+    pawn_hash: u64,
 }
 
 impl Board {
     pub fn empty() -> Board {
         Board {
-            board: [ColoredPiece::empty(); 64]
+            board: [ColoredPiece::empty(); 64],
+            hash: 0,
+            pawn_hash: 0,
         }
     }
 
     pub fn new() -> Board {
         let mut b = Board::empty();
         let pieces: [Piece; 8] = [Piece::Rook, Piece::Knight, Piece::Bishop, Piece::Queen, Piece::King, Piece::Bishop, Piece::Knight, Piece::Rook];
-        for x in 0..8 {
-            b.board[Pos::new(x, 7).index()] = pieces[7 - x].colored(Color::Black);
-            b.board[Pos::new(x, 6).index()] = Piece::Pawn.colored(Color::Black);
+        for (x, piece) in pieces.iter().enumerate() {
+            b.set_piece(Pos::new(x, 7), pieces[7 - x].colored(Color::Black));
+            b.set_piece(Pos::new(x, 6), Piece::Pawn.colored(Color::Black));
 
-            b.board[Pos::new(x, 0).index()] = pieces[x].colored(Color::White);
-            b.board[Pos::new(x, 1).index()] = Piece::Pawn.colored(Color::White);
+            b.set_piece(Pos::new(x, 0), piece.colored(Color::White));
+            b.set_piece(Pos::new(x, 1), Piece::Pawn.colored(Color::White));
         }
         b
     }
@@ -33,17 +41,17 @@ impl Board {
     pub fn test_board() -> Board {
         let mut b = Board::empty();
         let pieces: [Piece; 8] = [Piece::Rook, Piece::Knight, Piece::Bishop, Piece::Queen, Piece::King, Piece::Bishop, Piece::Knight, Piece::Rook];
-        for x in 0..8 {
-            b.board[Pos::new(x, 7).index()] = pieces[7 - x].colored(Color::Black);
-            b.board[Pos::new(x, 6).index()] = Piece::Pawn.colored(Color::Black);
-            b.board[Pos::new(x, 0).index()] = pieces[x].colored(Color::White);
+        for (x, piece) in pieces.iter().enumerate() {
+            b.set_piece(Pos::new(x, 7), pieces[7 - x].colored(Color::Black));
+            b.set_piece(Pos::new(x, 6), Piece::Pawn.colored(Color::Black));
+            b.set_piece(Pos::new(x, 0), piece.colored(Color::White));
         }
         b
     }
 
 
 
-    pub fn possible_moves(&self, color: Color) -> PossibleMoveIterator {
+    pub fn possible_moves(&self, color: Color) -> PossibleMoveIterator<'_> {
         PossibleMoveIterator::new(self, color)
     }
 
@@ -57,6 +65,32 @@ impl Board {
 
 
 
+    pub fn set_piece(&mut self, pos: Pos, piece: ColoredPiece) {
+        let old = self.board[pos.index()];
+        for p in &[old, piece] {
+            let key = zobrist::piece_key(*p, pos);
+            self.hash ^= key;
+            if p.piece == Piece::Pawn {
+                self.pawn_hash ^= key;
+            }
+        }
+        self.board[pos.index()] = piece;
+    }
+
+    pub fn clear_piece(&mut self, pos: Pos) {
+        self.set_piece(pos, ColoredPiece::empty());
+    }
+
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    pub fn pawn_hash(&self) -> u64 {
+        self.pawn_hash
+    }
+
+
+
     pub fn pieces(&self, col: Color) -> BitBoard {
         let mut b = BitBoard::empty();
         for i in 0..64 {
@@ -67,6 +101,26 @@ impl Board {
         b
     }
 
+    pub fn occupied(&self) -> BitBoard {
+        let mut b = BitBoard::empty();
+        for i in 0..64 {
+            if !self.board[i].is_empty() {
+                b.add(Pos::from_index(i));
+            }
+        }
+        b
+    }
+
+    pub fn pieces_of(&self, piece: Piece, col: Color) -> BitBoard {
+        let mut b = BitBoard::empty();
+        for i in 0..64 {
+            if self.board[i].piece == piece && self.board[i].color == col {
+                b.add(Pos::from_index(i));
+            }
+        }
+        b
+    }
+
 
     pub fn has_king(&self, color: Color) -> bool {
        self.king_pos(color).is_some()
@@ -86,8 +140,9 @@ impl Board {
 
 
     pub fn do_move(&mut self, m: Move) {
-        self.board[m.1.index()] = self.board[m.0.index()];
-        self.board[m.0.index()].piece = Piece::Empty;
+        let piece = self.board[m.0.index()];
+        self.set_piece(m.1, piece);
+        self.clear_piece(m.0);
     }
 
     pub fn moved(&self, m: Move) -> Board {
@@ -107,6 +162,70 @@ impl Board {
     pub fn is_valid_move(&self, m: Move) -> bool {
         possible_moves(self, m.0).piece_at(m.1)
     }
+
+
+    // Pieces of color attacking pos, only considering pieces in occupied
+    pub fn attackers(&self, pos: Pos, color: Color, occupied: BitBoard) -> BitBoard {
+        let mut b = BitBoard::empty();
+        for src in occupied.iter() {
+            if let Some(p) = self.piece_at(src) {
+                if p.color == color && attacks_for_piece(occupied, p, src).piece_at(pos) {
+                    b.add(src);
+                }
+            }
+        }
+        b
+    }
+
+    fn least_valuable_attacker(&self, pos: Pos, color: Color, occupied: BitBoard) -> Option<(Pos, Piece)> {
+        self.attackers(pos, color, occupied).iter()
+            .filter_map(|src| self.piece_at(src).map(|p| (src, p.piece)))
+            .min_by_key(|(_, p)| p.score())
+    }
+
+    // Static exchange evaluation: material balance (in Piece::score units) after the sequence of
+    // captures on the destination square of m, with both sides always recapturing with their least valuable piece.
+    // Attackers behind other pieces (x-rays) are found by removing each capturing piece from the occupancy.
+    pub fn see(&self, m: Move) -> i64 {
+        let attacker = match self.piece_at(m.0) {
+            Some(p) => p,
+            None => return 0
+        };
+
+        let mut gain = [0i64; 33];
+        let mut depth = 0;
+        gain[0] = self.piece_at(m.1).map(|p| p.piece.score()).unwrap_or(0);
+
+        let mut occupied = self.occupied();
+        let mut from = m.0;
+        let mut piece = attacker.piece;
+        let mut side = attacker.color;
+
+        loop {
+            depth += 1;
+            // Assume piece will be recaptured
+            gain[depth] = piece.score() - gain[depth - 1];
+            if depth + 1 >= gain.len() {
+                break;
+            }
+
+            occupied.remove(from);
+            side = side.inverse();
+            match self.least_valuable_attacker(m.1, side, occupied) {
+                Some((pos, p)) => {
+                    from = pos;
+                    piece = p;
+                }
+                None => break
+            }
+        }
+
+        while depth > 1 {
+            depth -= 1;
+            gain[depth - 1] = -cmp::max(-gain[depth - 1], gain[depth]);
+        }
+        gain[0]
+    }
 }
 
 
@@ -128,3 +247,34 @@ impl fmt::Display for Board {
         write!(f, "   a b c d e f g h\n")
     }
 }
+
+
+
+#[test]
+fn see_exchanges() {
+    let mut board = Board::empty();
+    board.set_piece(Pos::new(4, 0), Piece::King.colored(Color::White));
+    board.set_piece(Pos::new(4, 7), Piece::King.colored(Color::Black));
+
+    // d5 pawn defended by e6 pawn
+    board.set_piece(Pos::new(3, 4), Piece::Pawn.colored(Color::Black));
+    board.set_piece(Pos::new(4, 5), Piece::Pawn.colored(Color::Black));
+    board.set_piece(Pos::new(3, 0), Piece::Queen.colored(Color::White));
+    board.set_piece(Pos::new(2, 2), Piece::Knight.colored(Color::White));
+
+    let queen_takes = Move(Pos::new(3, 0), Pos::new(3, 4));
+    let knight_takes = Move(Pos::new(2, 2), Pos::new(3, 4));
+    // Qxd5 exd5 Nxd5
+    assert_eq!(board.see(queen_takes), 1 - 10 + 1);
+    // Nxd5 exd5 Qxd5
+    assert_eq!(board.see(knight_takes), 1 - 3 + 1);
+
+    // Rook on d4 hidden behind the queen
+    board.set_piece(Pos::new(3, 3), Piece::Rook.colored(Color::White));
+    board.clear_piece(Pos::new(2, 2));
+    assert_eq!(board.see(Move(Pos::new(3, 3), Pos::new(3, 4))), 1 - 5 + 1);
+
+    // Undefended
+    board.clear_piece(Pos::new(4, 5));
+    assert_eq!(board.see(queen_takes), 1);
+}
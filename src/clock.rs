@@ -0,0 +1,207 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+// Sessions are assumed to last this many moves when the time control doesn't say
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+pub const DEFAULT_TIME_TOLERANCE: Duration = Duration::from_millis(50);
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeControl {
+    SuddenDeath(Duration),
+    // Added after every move
+    Increment { base: Duration, increment: Duration },
+    // base is added again every moves moves, as in 40 moves in 90 minutes
+    MovesPerSession { moves: usize, base: Duration },
+    // Every move gets the same time, unused time is lost
+    PerMove(Duration),
+}
+
+impl TimeControl {
+    fn initial(&self) -> Duration {
+        match *self {
+            TimeControl::SuddenDeath(base) => base,
+            TimeControl::Increment { base, .. } => base,
+            TimeControl::MovesPerSession { base, .. } => base,
+            TimeControl::PerMove(time) => time,
+        }
+    }
+}
+
+fn parse_seconds(s: &str) -> Result<Duration, ()> {
+    s.parse::<f64>().ok().filter(|t| t.is_finite() && *t >= 0.0).map(Duration::from_secs_f64).ok_or(())
+}
+
+// Same notation as most tournament managers, times in seconds:
+// "60" sudden death, "60+0.5" with increment, "40/60" moves per session and "st=0.1" per move
+impl FromStr for TimeControl {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(time) = s.strip_prefix("st=") {
+            Ok(TimeControl::PerMove(parse_seconds(time)?))
+        } else if let Some((moves, base)) = s.split_once('/') {
+            let moves = moves.parse().map_err(|_| ())?;
+            if moves == 0 {
+                return Err(());
+            }
+            Ok(TimeControl::MovesPerSession { moves, base: parse_seconds(base)? })
+        } else if let Some((base, increment)) = s.split_once('+') {
+            Ok(TimeControl::Increment { base: parse_seconds(base)?, increment: parse_seconds(increment)? })
+        } else {
+            Ok(TimeControl::SuddenDeath(parse_seconds(s)?))
+        }
+    }
+}
+
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TimeControl::SuddenDeath(base) => write!(f, "{}", base.as_secs_f64()),
+            TimeControl::Increment { base, increment } => write!(f, "{}+{}", base.as_secs_f64(), increment.as_secs_f64()),
+            TimeControl::MovesPerSession { moves, base } => write!(f, "{}/{}", moves, base.as_secs_f64()),
+            TimeControl::PerMove(time) => write!(f, "st={}", time.as_secs_f64()),
+        }
+    }
+}
+
+
+// What a controller knows about the clocks when asked for a move
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockInfo {
+    pub remaining: Duration,
+    pub opponent_remaining: Duration,
+    pub increment: Duration,
+    // Moves left before the time control adds time, None when it never does
+    pub moves_to_go: Option<usize>,
+    // With a fixed time per move, remaining is all there is and nothing carries over
+    pub per_move: bool,
+}
+
+impl ClockInfo {
+    // Time a controller can reasonably spend on this move
+    pub fn budget(&self) -> Duration {
+        if self.per_move {
+            return self.remaining;
+        }
+        let moves = self.moves_to_go.map(|m| m as u32).unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+        let budget = self.remaining / moves + self.increment * 3 / 4;
+        budget.min(self.remaining / 2)
+    }
+}
+
+
+// Clocks of both players of a game, indexed like the players
+pub struct Clock {
+    control: TimeControl,
+    tolerance: Duration,
+    remaining: [Duration; 2],
+    moves: [usize; 2],
+}
+
+impl Clock {
+    pub fn new(control: TimeControl, tolerance: Duration) -> Clock {
+        Clock {
+            control,
+            tolerance,
+            remaining: [control.initial(); 2],
+            moves: [0; 2],
+        }
+    }
+
+    pub fn info(&self, player: usize) -> ClockInfo {
+        let (increment, moves_to_go) = match self.control {
+            TimeControl::Increment { increment, .. } => (increment, None),
+            TimeControl::MovesPerSession { moves, .. } => (Duration::ZERO, Some(moves - self.moves[player] % moves)),
+            _ => (Duration::ZERO, None),
+        };
+        ClockInfo {
+            remaining: self.remaining[player],
+            opponent_remaining: self.remaining[1 - player],
+            increment,
+            moves_to_go,
+            per_move: matches!(self.control, TimeControl::PerMove(_)),
+        }
+    }
+
+    // Charges a move's thinking time, returns false if the player's flag fell
+    pub fn punch(&mut self, player: usize, elapsed: Duration) -> bool {
+        if elapsed > self.remaining[player] + self.tolerance {
+            self.remaining[player] = Duration::ZERO;
+            return false;
+        }
+
+        self.moves[player] += 1;
+        let left = self.remaining[player].saturating_sub(elapsed);
+        self.remaining[player] = match self.control {
+            TimeControl::SuddenDeath(_) => left,
+            TimeControl::Increment { increment, .. } => left + increment,
+            TimeControl::MovesPerSession { moves, base } if self.moves[player] % moves == 0 => left + base,
+            TimeControl::MovesPerSession { .. } => left,
+            TimeControl::PerMove(time) => time,
+        };
+        true
+    }
+}
+
+
+
+#[test]
+fn clock_time_controls() {
+    let secs = Duration::from_secs;
+    assert_eq!("60".parse(), Ok(TimeControl::SuddenDeath(secs(60))));
+    assert_eq!("10+0.5".parse(), Ok(TimeControl::Increment { base: secs(10), increment: Duration::from_millis(500) }));
+    assert_eq!("40/90".parse(), Ok(TimeControl::MovesPerSession { moves: 40, base: secs(90) }));
+    assert_eq!("st=0.1".parse(), Ok(TimeControl::PerMove(Duration::from_millis(100))));
+    assert_eq!("x".parse::<TimeControl>(), Err(()));
+    assert_eq!("0/10".parse::<TimeControl>(), Err(()));
+
+    let mut clock = Clock::new("2/10".parse().unwrap(), Duration::ZERO);
+    assert_eq!(clock.info(0).moves_to_go, Some(2));
+    assert!(clock.punch(0, secs(4)));
+    assert_eq!(clock.info(0).moves_to_go, Some(1));
+    assert!(clock.punch(0, secs(4)));
+    // New session
+    assert_eq!(clock.info(0).remaining, secs(12));
+    assert_eq!(clock.info(1).opponent_remaining, secs(12));
+    assert!(!clock.punch(0, secs(13)));
+    assert_eq!(clock.info(0).remaining, Duration::ZERO);
+
+    let mut clock = Clock::new("5+1".parse().unwrap(), Duration::from_millis(100));
+    assert!(clock.punch(1, Duration::from_millis(5050)));
+    assert_eq!(clock.info(1).remaining, secs(1));
+}
+
+#[test]
+fn clock_flag_fall_loses() {
+    use crate::ai::*;
+    use crate::board::*;
+    use crate::elo::*;
+    use crate::piece::*;
+    use crate::player::*;
+
+    struct SlowAI(FirstMoveAI);
+
+    impl PlayerController for SlowAI {
+        fn name(&self) -> String {
+            "Slow".to_string()
+        }
+
+        fn play(&self, color: Color, board: &Board) -> Action {
+            std::thread::sleep(Duration::from_millis(30));
+            self.0.play(color, board)
+        }
+    }
+
+    let settings = GameSettings {
+        time_control: Some(TimeControl::PerMove(Duration::from_millis(10))),
+        time_tolerance: Duration::from_millis(5),
+        ..GameSettings::new()
+    };
+    let (fast, slow) = (FirstMoveAI::new(), SlowAI(FirstMoveAI::new()));
+    let outcome = play_once_with_white([&fast, &slow], &settings, 1, 0);
+    assert_eq!(outcome.termination, Termination::TimeForfeit);
+    assert_eq!(outcome.winner, Some(0));
+}
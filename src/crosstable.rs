@@ -0,0 +1,202 @@
+use crate::tournament::*;
+
+use std::fmt;
+use std::fmt::Write;
+
+const WHITE: usize = 0;
+const BLACK: usize = 1;
+
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HeadToHead {
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+}
+
+impl HeadToHead {
+    pub fn games(&self) -> usize {
+        self.wins + self.draws + self.losses
+    }
+
+    // Fraction of points scored, None when no games were played
+    pub fn score(&self) -> Option<f64> {
+        if self.games() == 0 {
+            None
+        } else {
+            Some((self.wins as f64 + self.draws as f64 / 2.0) / self.games() as f64)
+        }
+    }
+
+    fn add(&self, other: &HeadToHead) -> HeadToHead {
+        HeadToHead {
+            wins: self.wins + other.wins,
+            draws: self.draws + other.draws,
+            losses: self.losses + other.losses,
+        }
+    }
+}
+
+impl fmt::Display for HeadToHead {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "+{} ={} -{}", self.wins, self.draws, self.losses)?;
+        if let Some(score) = self.score() {
+            write!(f, " ({:.1}%)", score * 100.0)?;
+        }
+        Ok(())
+    }
+}
+
+
+// Results of every player against every other player, split by the colour the player had
+pub struct Crosstable {
+    names: Vec<String>,
+    // Indexed by player * size + opponent, then by the player's colour
+    cells: Vec<[HeadToHead; 2]>,
+}
+
+impl Crosstable {
+    pub fn new(names: Vec<String>) -> Crosstable {
+        let size = names.len();
+        Crosstable {
+            names,
+            cells: vec![[HeadToHead::default(); 2]; size * size],
+        }
+    }
+
+    pub fn from_results(names: Vec<String>, results: &[GameResult]) -> Crosstable {
+        let mut crosstable = Crosstable::new(names);
+        for r in results {
+            crosstable.add(r);
+        }
+        crosstable
+    }
+
+    pub fn add(&mut self, result: &GameResult) {
+        let (white, black) = (result.white_player(), result.black_player());
+        let winner = result.winner.map(|w| if w == 0 { result.players.0 } else { result.players.1 });
+
+        let size = self.names.len();
+        let (w, b) = (white * size + black, black * size + white);
+        match winner {
+            Some(p) if p == white => {
+                self.cells[w][WHITE].wins += 1;
+                self.cells[b][BLACK].losses += 1;
+            }
+            Some(_) => {
+                self.cells[w][WHITE].losses += 1;
+                self.cells[b][BLACK].wins += 1;
+            }
+            None => {
+                self.cells[w][WHITE].draws += 1;
+                self.cells[b][BLACK].draws += 1;
+            }
+        }
+    }
+
+    pub fn as_white(&self, player: usize, opponent: usize) -> HeadToHead {
+        self.cells[player * self.names.len() + opponent][WHITE]
+    }
+
+    pub fn as_black(&self, player: usize, opponent: usize) -> HeadToHead {
+        self.cells[player * self.names.len() + opponent][BLACK]
+    }
+
+    pub fn head_to_head(&self, player: usize, opponent: usize) -> HeadToHead {
+        self.as_white(player, opponent).add(&self.as_black(player, opponent))
+    }
+
+    // Per pairing breakdown, from the point of view of the first player
+    pub fn details(&self) -> String {
+        let mut out = String::new();
+        for a in 0..self.names.len() {
+            for b in a + 1..self.names.len() {
+                if self.head_to_head(a, b).games() == 0 {
+                    continue;
+                }
+                writeln!(out, "{} vs {}: {}", self.names[a], self.names[b], self.head_to_head(a, b)).unwrap();
+                writeln!(out, "  as white: {}", self.as_white(a, b)).unwrap();
+                writeln!(out, "  as black: {}", self.as_black(a, b)).unwrap();
+            }
+        }
+        out
+    }
+
+    // One line per ordered pair and colour
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("player,opponent,color,wins,draws,losses,score\n");
+        for a in 0..self.names.len() {
+            for b in 0..self.names.len() {
+                for (color, h) in [("white", self.as_white(a, b)), ("black", self.as_black(a, b))] {
+                    if h.games() == 0 {
+                        continue;
+                    }
+                    let score = h.score().unwrap_or(0.0);
+                    writeln!(out, "{},{},{},{},{},{},{:.4}", self.names[a], self.names[b], color, h.wins, h.draws, h.losses, score).unwrap();
+                }
+            }
+        }
+        out
+    }
+}
+
+// Score of the row player against the column player
+impl fmt::Display for Crosstable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = self.names.iter().map(|n| n.len()).max().unwrap_or(0);
+
+        write!(f, "{:width$}", "", width = width)?;
+        for name in &self.names {
+            write!(f, " {:>width$}", name, width = width)?;
+        }
+        writeln!(f)?;
+
+        for a in 0..self.names.len() {
+            write!(f, "{:width$}", self.names[a], width = width)?;
+            for b in 0..self.names.len() {
+                let cell = match self.head_to_head(a, b).score() {
+                    Some(score) if a != b => format!("{:.1}%", score * 100.0),
+                    _ => "-".to_string(),
+                };
+                write!(f, " {:>width$}", cell, width = width)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+
+
+#[test]
+fn crosstable_splits_by_color() {
+    use crate::elo::Termination;
+    use std::time::Duration;
+
+    let names = vec!["A".to_string(), "B".to_string()];
+    let result = |players, winner: Option<usize>, white| GameResult {
+        players,
+        winner,
+        white,
+        moves: 10,
+        seed: 0,
+        termination: if winner.is_some() { Termination::KingCaptured } else { Termination::MoveLimit },
+        duration: Duration::ZERO,
+        opening: None,
+    };
+    let results = vec![
+        // A wins as white
+        result((0, 1), Some(0), 0),
+        // A loses as black
+        result((1, 0), Some(0), 0),
+        // Draw, A is black
+        result((0, 1), None, 1),
+    ];
+    let crosstable = Crosstable::from_results(names, &results);
+
+    assert_eq!(crosstable.as_white(0, 1), HeadToHead { wins: 1, draws: 0, losses: 0 });
+    assert_eq!(crosstable.as_black(0, 1), HeadToHead { wins: 0, draws: 1, losses: 1 });
+    assert_eq!(crosstable.head_to_head(1, 0), HeadToHead { wins: 1, draws: 1, losses: 1 });
+    assert_eq!(crosstable.head_to_head(0, 1).score(), Some(0.5));
+    assert_eq!(crosstable.to_csv().lines().count(), 5);
+}
@@ -0,0 +1,383 @@
+use crate::elo::*;
+use crate::piece::*;
+use crate::rating::*;
+use crate::tournament::*;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+// Ratings kept across runs, in a directory holding two tab separated files:
+// games.tsv is the append-only history of every game, ratings.tsv a snapshot of the ratings it leads to.
+// The snapshot is what gets loaded, the history is only read back to recompute it.
+
+const GAMES_FILE: &str = "games.tsv";
+const RATINGS_FILE: &str = "ratings.tsv";
+
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PlayerKey {
+    pub name: String,
+    pub version: String,
+}
+
+impl PlayerKey {
+    pub fn new(name: &str, version: &str) -> PlayerKey {
+        PlayerKey {
+            name: name.to_string(),
+            version: version.to_string(),
+        }
+    }
+
+    pub fn of(player: &EloPlayer) -> PlayerKey {
+        PlayerKey::new(&player.name(), &player.version())
+    }
+}
+
+impl fmt::Display for PlayerKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}@{}", self.name, self.version)
+    }
+}
+
+
+#[derive(Debug, Clone)]
+pub struct DbEntry {
+    pub key: PlayerKey,
+    pub elo: Elo,
+    pub wins: usize,
+    pub losses: usize,
+    pub draws: usize,
+}
+
+impl DbEntry {
+    fn new(key: PlayerKey) -> DbEntry {
+        DbEntry {
+            key,
+            elo: Elo::new(),
+            wins: 0,
+            losses: 0,
+            draws: 0,
+        }
+    }
+
+    pub fn games(&self) -> usize {
+        self.wins + self.losses + self.draws
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredGame {
+    pub white: PlayerKey,
+    pub black: PlayerKey,
+    pub winner: Option<Color>,
+    pub seed: u64,
+    pub moves: usize,
+}
+
+
+pub struct RatingsDb {
+    dir: PathBuf,
+    entries: Vec<DbEntry>,
+    index: HashMap<PlayerKey, usize>,
+}
+
+impl RatingsDb {
+    // Creates the directory if needed
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<RatingsDb> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let mut db = RatingsDb {
+            dir,
+            entries: Vec::new(),
+            index: HashMap::new(),
+        };
+        let path = db.dir.join(RATINGS_FILE);
+        if path.exists() {
+            for line in BufReader::new(File::open(path)?).lines() {
+                let entry = parse_entry(&line?)?;
+                db.index.insert(entry.key.clone(), db.entries.len());
+                db.entries.push(entry);
+            }
+        }
+        Ok(db)
+    }
+
+    pub fn entries(&self) -> &[DbEntry] {
+        &self.entries
+    }
+
+    pub fn get(&self, key: &PlayerKey) -> Option<&DbEntry> {
+        self.index.get(key).map(|&i| &self.entries[i])
+    }
+
+    fn entry(&mut self, key: &PlayerKey) -> usize {
+        if let Some(&i) = self.index.get(key) {
+            return i;
+        }
+        self.index.insert(key.clone(), self.entries.len());
+        self.entries.push(DbEntry::new(key.clone()));
+        self.entries.len() - 1
+    }
+
+    // Ratings are stored by name and version, so two participants sharing both would be one player
+    pub fn check_players(players: &[EloPlayer]) -> io::Result<()> {
+        let keys = players.iter().map(PlayerKey::of).collect::<Vec<_>>();
+        for (i, key) in keys.iter().enumerate() {
+            if keys[..i].contains(key) {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} is entered twice", key)));
+            }
+        }
+        Ok(())
+    }
+
+    // Starts players from their stored ratings and game counts
+    pub fn load_ratings(&self, players: &mut [EloPlayer]) {
+        for player in players {
+            if let Some(entry) = self.get(&PlayerKey::of(player)) {
+                player.set_elo_score(entry.elo.score());
+                player.set_record(entry.wins, entry.losses, entry.draws);
+            }
+        }
+    }
+
+    // Appends the games to the history and updates the ratings, in the same order as the tournament did
+    pub fn record(&mut self, results: &[GameResult], players: &[EloPlayer]) -> io::Result<()> {
+        RatingsDb::check_players(players)?;
+        let keys = players.iter().map(PlayerKey::of).collect::<Vec<_>>();
+        let games = results
+            .iter()
+            .map(|r| StoredGame {
+                white: keys[r.white_player()].clone(),
+                black: keys[r.black_player()].clone(),
+                winner: r.winner_color(),
+                seed: r.seed,
+                moves: r.moves,
+            })
+            .collect::<Vec<_>>();
+
+        let mut file = OpenOptions::new().create(true).append(true).open(self.dir.join(GAMES_FILE))?;
+        let mut out = String::new();
+        for game in &games {
+            out.push_str(&format_game(game));
+            self.apply(game)?;
+        }
+        file.write_all(out.as_bytes())?;
+
+        self.save()
+    }
+
+    pub fn history(&self) -> io::Result<Vec<StoredGame>> {
+        let path = self.dir.join(GAMES_FILE);
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        BufReader::new(File::open(path)?).lines().map(|line| parse_game(&line?)).collect()
+    }
+
+    // Rebuilds every rating from the full history, e.g. after the snapshot was lost or edited
+    pub fn recompute(&mut self) -> io::Result<()> {
+        self.entries.clear();
+        self.index.clear();
+        for game in self.history()? {
+            self.apply(&game)?;
+        }
+        self.save()
+    }
+
+    // Results of the whole history for a maximum likelihood fit, players are in entries() order
+    pub fn result_matrix(&mut self) -> io::Result<ResultMatrix> {
+        let history = self.history()?;
+        for game in &history {
+            self.entry(&game.white);
+            self.entry(&game.black);
+        }
+
+        let mut matrix = ResultMatrix::new(self.entries.len());
+        for game in &history {
+            matrix.add(self.index[&game.white], self.index[&game.black], game.winner);
+        }
+        Ok(matrix)
+    }
+
+    fn apply(&mut self, game: &StoredGame) -> io::Result<()> {
+        if game.white == game.black {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} plays against itself in ratings database", game.white)));
+        }
+        let (w, b) = (self.entry(&game.white), self.entry(&game.black));
+
+        let (first, second) = (w.min(b), w.max(b));
+        let (left, right) = self.entries.split_at_mut(second);
+        let (first, second) = (&mut left[first], &mut right[0]);
+        let (white, black) = if w < b { (first, second) } else { (second, first) };
+
+        match game.winner {
+            Some(Color::White) => {
+                white.elo.win(&mut black.elo, ELO_K);
+                white.wins += 1;
+                black.losses += 1;
+            }
+            Some(Color::Black) => {
+                black.elo.win(&mut white.elo, ELO_K);
+                black.wins += 1;
+                white.losses += 1;
+            }
+            None => {
+                white.draws += 1;
+                black.draws += 1;
+            }
+        }
+        Ok(())
+    }
+
+    // Written to a temporary file first so an interrupted run can't leave a truncated snapshot
+    fn save(&self) -> io::Result<()> {
+        let mut out = String::new();
+        for e in &self.entries {
+            out.push_str(&format!("{}\t{}\t{}\t{}\t{}\t{}\n", escape(&e.key.name), escape(&e.key.version), e.elo.score(), e.wins, e.losses, e.draws));
+        }
+        let tmp = self.dir.join(format!("{}.tmp", RATINGS_FILE));
+        fs::write(&tmp, out)?;
+        fs::rename(tmp, self.dir.join(RATINGS_FILE))
+    }
+}
+
+
+// Names come from controllers and engines, tabs and line breaks in them would break the format
+fn escape(field: &str) -> String {
+    let mut out = String::new();
+    for c in field.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn unescape(field: &str, line: &str) -> io::Result<String> {
+    let mut out = String::new();
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => out.push('\\'),
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            _ => return Err(invalid(line)),
+        }
+    }
+    Ok(out)
+}
+
+fn invalid(line: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Invalid line in ratings database: {}", line))
+}
+
+fn parse_entry(line: &str) -> io::Result<DbEntry> {
+    let fields = line.split('\t').collect::<Vec<_>>();
+    if fields.len() != 6 {
+        return Err(invalid(line));
+    }
+    let number = |s: &str| s.parse::<usize>().map_err(|_| invalid(line));
+    Ok(DbEntry {
+        key: PlayerKey::new(&unescape(fields[0], line)?, &unescape(fields[1], line)?),
+        elo: Elo::with_score(fields[2].parse().map_err(|_| invalid(line))?),
+        wins: number(fields[3])?,
+        losses: number(fields[4])?,
+        draws: number(fields[5])?,
+    })
+}
+
+fn format_game(game: &StoredGame) -> String {
+    let result = match game.winner {
+        Some(Color::White) => "1-0",
+        Some(Color::Black) => "0-1",
+        None => "1/2-1/2",
+    };
+    format!("{}\t{}\t{}\t{}\t{}\t{}\t{}\n", escape(&game.white.name), escape(&game.white.version), escape(&game.black.name), escape(&game.black.version), result, game.seed, game.moves)
+}
+
+fn parse_game(line: &str) -> io::Result<StoredGame> {
+    let fields = line.split('\t').collect::<Vec<_>>();
+    if fields.len() != 7 {
+        return Err(invalid(line));
+    }
+    let winner = match fields[4] {
+        "1-0" => Some(Color::White),
+        "0-1" => Some(Color::Black),
+        "1/2-1/2" => None,
+        _ => return Err(invalid(line)),
+    };
+    Ok(StoredGame {
+        white: PlayerKey::new(&unescape(fields[0], line)?, &unescape(fields[1], line)?),
+        black: PlayerKey::new(&unescape(fields[2], line)?, &unescape(fields[3], line)?),
+        winner,
+        seed: fields[5].parse().map_err(|_| invalid(line))?,
+        moves: fields[6].parse().map_err(|_| invalid(line))?,
+    })
+}
+
+
+
+#[test]
+fn database_survives_reopening() {
+    use crate::ai::*;
+
+    let dir = std::env::temp_dir().join(format!("chess-ratings-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+
+    let players = vec![EloPlayer::new(FirstMoveAI::new), EloPlayer::new(SwarmAI::new)];
+    let mut tournament = Tournament::new(players);
+    tournament.set_seed(5);
+    tournament.set_threads(1);
+
+    let mut db = RatingsDb::open(&dir).unwrap();
+    let first = tournament.play(&[(0, 1), (1, 0)]);
+    db.record(&first, &tournament.players).unwrap();
+    let second = tournament.play(&[(0, 1), (1, 0), (0, 1)]);
+    db.record(&second, &tournament.players).unwrap();
+
+    // Same order of updates as the tournament, so the same ratings
+    let swarm = PlayerKey::of(&tournament.players[1]);
+    let entry = RatingsDb::open(&dir).unwrap().get(&swarm).cloned().unwrap();
+    assert_eq!(entry.elo.score(), tournament.players[1].elo_score());
+    assert_eq!(entry.games(), 5);
+
+    let mut db = RatingsDb::open(&dir).unwrap();
+    assert_eq!(db.history().unwrap().len(), 5);
+    db.recompute().unwrap();
+    assert_eq!(db.get(&swarm).unwrap().elo.score(), entry.elo.score());
+    assert_eq!(db.result_matrix().unwrap().size(), 2);
+
+    // A new run continues from the stored games
+    let mut players = vec![EloPlayer::new(FirstMoveAI::new), EloPlayer::new(SwarmAI::new)];
+    db.load_ratings(&mut players);
+    assert_eq!(players[1].games(), 5);
+    assert_eq!((players[1].victories, players[1].loses, players[1].draws), (entry.wins, entry.losses, entry.draws));
+
+    // Names with separators in them are stored escaped
+    let odd = PlayerKey::new("Engine\t2\n", "v\\1");
+    let game = StoredGame { white: odd.clone(), black: swarm.clone(), winner: None, seed: 1, moves: 2 };
+    assert_eq!(parse_game(format_game(&game).trim_end_matches('\n')).unwrap(), game);
+
+    // Two participants with the same name and version can't be told apart
+    let twins = vec![EloPlayer::new(FirstMoveAI::new), EloPlayer::new(FirstMoveAI::new)];
+    assert_eq!(RatingsDb::check_players(&twins).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    let mirror = format_game(&StoredGame { white: swarm.clone(), black: swarm.clone(), winner: None, seed: 1, moves: 2 });
+    OpenOptions::new().append(true).open(dir.join(GAMES_FILE)).unwrap().write_all(mirror.as_bytes()).unwrap();
+    assert_eq!(db.recompute().unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
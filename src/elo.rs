@@ -1,12 +1,21 @@
 use crate::player::*;
-use crate::board::*;
 use crate::piece::*;
+use crate::opening::*;
+use crate::adjudication::*;
+use crate::clock::*;
+use crate::board::*;
+use crate::zobrist;
+
+use std::time::{Duration, Instant};
 
-use rand::{thread_rng, Rng};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
 
-const ELO_STARTING_SCORE : i64 = 1200;
-const ELO_K : f64 = 1.0;
-const MAX_MOVES : usize = 100;
+pub const ELO_STARTING_SCORE : i64 = 1200;
+pub const ELO_K : f64 = 1.0;
+pub const MAX_MOVES : usize = 100;
+// Draw claims without a threefold repetition a player can make in a turn before it loses, a controller that only claims would never move
+const MAX_DECLINED_CLAIMS : usize = 3;
 
 
 #[derive(Debug, Clone, Copy)]
@@ -15,8 +24,80 @@ pub struct Elo {
 }
 
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Termination {
+    KingCaptured,
+    Resignation,
+    // Offered by one player and accepted by the other
+    DrawAgreed,
+    // Claimed by the player to move
+    Repetition,
+    // An illegal move, or an action that wasn't allowed at that point
+    IllegalMove,
+    MoveLimit,
+    Adjudicated(AdjudicationReason),
+    TimeForfeit,
+}
+
+impl Termination {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Termination::KingCaptured => "king captured",
+            Termination::Resignation => "resignation",
+            Termination::DrawAgreed => "draw agreement",
+            Termination::Repetition => "threefold repetition",
+            Termination::IllegalMove => "illegal move",
+            Termination::MoveLimit => "move limit",
+            Termination::Adjudicated(reason) => reason.name(),
+            Termination::TimeForfeit => "time forfeit",
+        }
+    }
+}
+
+
+// How games are run, shared by every game of a tournament
+#[derive(Debug, Clone, Copy)]
+pub struct GameSettings {
+    pub max_moves: usize,
+    pub adjudication: Adjudication,
+    pub time_control: Option<TimeControl>,
+    // Extra time allowed before a flag falls, to absorb scheduling noise
+    pub time_tolerance: Duration,
+}
+
+impl GameSettings {
+    pub fn new() -> GameSettings {
+        GameSettings {
+            max_moves: MAX_MOVES,
+            adjudication: Adjudication::new(),
+            time_control: None,
+            time_tolerance: DEFAULT_TIME_TOLERANCE,
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Copy)]
+pub struct GameOutcome {
+    // Index of the winner in the players given to play_once, None for a draw
+    pub winner: Option<usize>,
+    // Index of the player who had white
+    pub white: usize,
+    pub moves: usize,
+    pub termination: Termination,
+    // Thinking time of each player
+    pub time_used: [Duration; 2],
+}
+
+
+pub type ControllerFactory = Box<dyn Fn() -> Box<dyn PlayerController> + Send + Sync>;
+
 pub struct EloPlayer {
-    controller: Box<dyn PlayerController>,
+    // Builds a fresh controller for every game, on whichever worker thread plays it, so controllers
+    // don't need to be shareable and keep no state from one game to the next
+    factory: ControllerFactory,
+    name: String,
+    version: String,
     elo: Elo,
 
     pub victories: usize,
@@ -30,11 +111,19 @@ pub struct EloPlayer {
 
 impl Elo {
     pub fn new() -> Elo {
+        Elo::with_score(ELO_STARTING_SCORE as f64)
+    }
+
+    pub fn with_score(score: f64) -> Elo {
         Elo {
-            score: ELO_STARTING_SCORE as f64,
+            score,
         }
     }
 
+    pub fn score(&self) -> f64 {
+        self.score
+    }
+
     pub fn win(&mut self, other: &mut Elo, k: f64) {
         let diff = k * (1.0 - self.win_probability(*other));
         self.score += diff;
@@ -56,9 +145,17 @@ impl Elo {
 
 
 impl EloPlayer {
-    pub fn new<T: 'static + PlayerController>(player: T) -> EloPlayer {
+    pub fn new<T: 'static + PlayerController, F: 'static + Fn() -> T + Send + Sync>(factory: F) -> EloPlayer {
+        let controller = factory();
+        EloPlayer::new_with_controller(&controller, move || Box::new(factory()))
+    }
+
+    // Takes the name and version from a controller that was already built, instead of building one more
+    pub fn new_with_controller<F: 'static + Fn() -> Box<dyn PlayerController> + Send + Sync>(controller: &dyn PlayerController, factory: F) -> EloPlayer {
         EloPlayer {
-            controller: Box::new(player),
+            factory: Box::new(factory),
+            name: controller.name(),
+            version: controller.version(),
             elo: Elo::new(),
 
             victories: 0,
@@ -68,13 +165,37 @@ impl EloPlayer {
     }
 
     pub fn name(&self) -> String {
-        self.controller.name()
+        self.name.clone()
+    }
+
+    pub fn controller(&self) -> Box<dyn PlayerController> {
+        (self.factory)()
+    }
+
+    pub fn version(&self) -> String {
+        self.version.clone()
     }
 
     pub fn elo_score(&self) -> f64 {
         self.elo.score
     }
 
+    // Continues from a rating of a previous run
+    pub fn set_elo_score(&mut self, score: f64) {
+        self.elo = Elo::with_score(score);
+    }
+
+    // Continues from the games of a previous run
+    pub fn set_record(&mut self, victories: usize, loses: usize, draws: usize) {
+        self.victories = victories;
+        self.loses = loses;
+        self.draws = draws;
+    }
+
+    pub fn games(&self) -> usize {
+        self.victories + self.loses + self.draws
+    }
+
     pub fn win(&mut self, other: &mut EloPlayer, k: f64) {
         self.elo.win(&mut other.elo, k);
         self.victories += 1;
@@ -86,63 +207,162 @@ impl EloPlayer {
         other.draws += 1;
     }
 
-    pub fn play_once(&mut self, other: &mut EloPlayer) -> usize {
-        let players = [&(*self.controller), &(*other.controller)];
-        let (result, moves) = play_once(players, MAX_MOVES);
+    // result is the index of the winner, 0 for self and 1 for other
+    pub fn record(&mut self, other: &mut EloPlayer, result: Option<usize>) {
         match result {
             Some(0) => self.win(other, ELO_K),
             Some(1) => other.win(self, ELO_K),
             _ => self.draw(other)
         }
-        moves
     }
 }
 
 
 
-fn play_once(players: [&dyn PlayerController; 2], max_moves: usize) -> (Option<usize>, usize) {
-    let mut board = Board::new();
+// Everything random in a game derives from seed, so a game between fresh controllers can be replayed exactly
+pub fn play_once(players: [&dyn PlayerController; 2], settings: &GameSettings, seed: u64) -> GameOutcome {
+    play_seeded(players, settings, seed, None, &Opening::start())
+}
+
+// Same as play_once with white chosen by the caller, so a seed can be replayed with colours reversed
+pub fn play_once_with_white(players: [&dyn PlayerController; 2], settings: &GameSettings, seed: u64, white: usize) -> GameOutcome {
+    assert!(white < 2);
+    play_seeded(players, settings, seed, Some(white), &Opening::start())
+}
 
-    let mut index = thread_rng().gen_range(0, 2);
-    let colors = if index == 1 {
+// Starts from the opening position instead, with its side to move
+pub fn play_opening(players: [&dyn PlayerController; 2], settings: &GameSettings, seed: u64, white: usize, opening: &Opening) -> GameOutcome {
+    assert!(white < 2);
+    play_seeded(players, settings, seed, Some(white), opening)
+}
+
+fn play_seeded(players: [&dyn PlayerController; 2], settings: &GameSettings, seed: u64, white: Option<usize>, opening: &Opening) -> GameOutcome {
+    let mut board = opening.board.clone();
+    let mut adjudicator = settings.adjudication.is_enabled().then(|| Adjudicator::new(settings.adjudication));
+    let mut clock = settings.time_control.map(|tc| Clock::new(tc, settings.time_tolerance));
+    let mut time_used = [Duration::ZERO; 2];
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    for p in &players {
+        p.reseed(rng.gen());
+    }
+
+    let coin_flip = rng.gen_range(0, 2);
+    let white = white.unwrap_or(coin_flip);
+    let colors = if white == 1 {
         [Color::Black, Color::White]
     } else {
         [Color::White, Color::Black]
     };
-    assert!(colors[index] == Color::White);
-    
+    assert!(colors[white] == Color::White);
+    let mut index = if opening.to_move == Color::White { white } else { 1 - white };
+
+    for (p, color) in players.iter().zip(colors) {
+        p.new_game(color, &board);
+    }
+
+    // Every position so far with the player to move, only searched when a draw is claimed
+    let mut positions = vec![position_key(&board, colors[index])];
+
     let mut moves = 0;
-    loop {
+    let (winner, termination) = loop {
         let color = colors[index];
 
         if !board.has_king(color) {
-            break;
+            break (Some(1 - index), Termination::KingCaptured);
         }
 
         moves += 1;
-        if moves >= max_moves {
-            return (None, max_moves);
+        if moves >= settings.max_moves {
+            break (None, Termination::MoveLimit);
         }
 
-        if let Some(m) = players[index].play(color, &board) {
-            match board.try_move(m) {
-                Ok(b) => {
-                    board = b;
-                    index = 1 - index;
+        let start = Instant::now();
+        let mut declined_claims = 0;
+        let played = loop {
+            let played = match &clock {
+                Some(clock) => players[index].play_with_clock(color, &board, &clock.info(index)),
+                None => players[index].play(color, &board),
+            };
+            // An unfounded claim is declined and the player asked again, on the same clock
+            if played == Action::ClaimDraw && repetitions(&positions) < 3 && declined_claims < MAX_DECLINED_CLAIMS {
+                println!("Draw claim declined, the position didn't occur three times ({}).", color);
+                declined_claims += 1;
+                continue;
+            }
+            break played;
+        };
+        let elapsed = start.elapsed();
+        time_used[index] += elapsed;
+        if let Some(clock) = &mut clock {
+            if !clock.punch(index, elapsed) {
+                break (Some(1 - index), Termination::TimeForfeit);
+            }
+        }
+
+        let (m, offer) = match played {
+            Action::Move(m) => (m, false),
+            Action::OfferDraw(m) => (m, true),
+            Action::Resign => break (Some(1 - index), Termination::Resignation),
+            Action::ClaimDraw if repetitions(&positions) >= 3 => break (None, Termination::Repetition),
+            Action::ClaimDraw | Action::AcceptDraw | Action::DeclineDraw => {
+                println!("Invalid action {:?} ({}).", played, color);
+                break (Some(1 - index), Termination::IllegalMove);
+            }
+        };
+        match board.try_move(m) {
+            Ok(b) => {
+                board = b;
+                index = 1 - index;
+
+                // Checked before adjudicating, which doesn't see missing kings
+                if !board.has_king(colors[index]) {
+                    break (Some(1 - index), Termination::KingCaptured);
+                }
+
+                players[index].opponent_moved(m, &board);
+                positions.push(position_key(&board, colors[index]));
+
+                if let Some((winner, reason)) = adjudicator.as_mut().and_then(|a| a.update(&board, moves)) {
+                    break (winner.map(|c| if c == Color::White { white } else { 1 - white }), Termination::Adjudicated(reason));
                 }
 
-                Err(_) => {
-                    println!("Invalid move ({}).", color);
-                    break;
+                if offer && players[index].respond_to_draw(colors[index], &board) == Action::AcceptDraw {
+                    break (None, Termination::DrawAgreed);
                 }
             }
-        } else {
-            break;
+
+            Err(_) => {
+                println!("Invalid move ({}).", color);
+                break (Some(1 - index), Termination::IllegalMove);
+            }
         }
     };
 
-    let winner = 1 - index;
-    debug_assert!(board.has_king(colors[winner]));
+    debug_assert!(termination != Termination::KingCaptured || winner.map(|w| board.has_king(colors[w])).unwrap_or(false));
+    for (i, p) in players.iter().enumerate() {
+        let result = match winner {
+            Some(w) if w == i => PlayerResult::Win,
+            Some(_) => PlayerResult::Loss,
+            None => PlayerResult::Draw,
+        };
+        p.game_over(result, termination);
+    }
+
+    GameOutcome {
+        winner,
+        white,
+        moves,
+        termination,
+        time_used,
+    }
+}
+
+fn position_key(board: &Board, to_move: Color) -> u64 {
+    board.hash() ^ zobrist::side_key(to_move)
+}
 
-    (Some(winner), moves)
+// Occurrences of the last position
+fn repetitions(positions: &[u64]) -> usize {
+    positions.last().map(|last| positions.iter().filter(|&p| p == last).count()).unwrap_or(0)
 }
@@ -0,0 +1,589 @@
+use crate::bitboard::*;
+use crate::board::*;
+use crate::piece::*;
+use crate::pos::*;
+use crate::moves::*;
+use crate::pawns::*;
+
+use std::fmt;
+use std::ops::{Add, AddAssign, Sub, Neg, Mul};
+
+// Tables and values from PeSTO (https://www.chessprogramming.org/PeSTO%27s_Evaluation_Function)
+
+pub const MAX_PHASE: i64 = 24;
+
+const KING_DANGER_MAX: i64 = 500;
+const SHIELD_ADVANCED: Score = Score::new(-10, 0);
+const SHIELD_MISSING: Score = Score::new(-20, 0);
+const SEMI_OPEN_FILE_NEAR_KING: Score = Score::new(-12, 0);
+const OPEN_FILE_NEAR_KING: Score = Score::new(-25, 0);
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Score {
+    pub mg: i64,
+    pub eg: i64,
+}
+
+impl Score {
+    pub const fn new(mg: i64, eg: i64) -> Score {
+        Score {
+            mg,
+            eg
+        }
+    }
+
+    pub fn zero() -> Score {
+        Score::new(0, 0)
+    }
+
+    // phase goes from MAX_PHASE (all pieces on the board) down to 0 (bare kings and pawns)
+    pub fn taper(&self, phase: i64) -> i64 {
+        let phase = phase.clamp(0, MAX_PHASE);
+        (self.mg * phase + self.eg * (MAX_PHASE - phase)) / MAX_PHASE
+    }
+}
+
+impl Add for Score {
+    type Output = Score;
+
+    fn add(self, other: Score) -> Score {
+        Score::new(self.mg + other.mg, self.eg + other.eg)
+    }
+}
+
+impl AddAssign for Score {
+    fn add_assign(&mut self, other: Score) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Score {
+    type Output = Score;
+
+    fn sub(self, other: Score) -> Score {
+        Score::new(self.mg - other.mg, self.eg - other.eg)
+    }
+}
+
+impl Neg for Score {
+    type Output = Score;
+
+    fn neg(self) -> Score {
+        Score::new(-self.mg, -self.eg)
+    }
+}
+
+impl Mul<i64> for Score {
+    type Output = Score;
+
+    fn mul(self, k: i64) -> Score {
+        Score::new(self.mg * k, self.eg * k)
+    }
+}
+
+
+
+
+pub fn piece_value(piece: Piece) -> Score {
+    match piece {
+        Piece::Empty => Score::new(0, 0),
+        Piece::Pawn => Score::new(82, 94),
+        Piece::Knight => Score::new(337, 281),
+        Piece::Bishop => Score::new(365, 297),
+        Piece::Rook => Score::new(477, 512),
+        Piece::Queen => Score::new(1025, 936),
+        // Losing the king loses the game, which is handled by the search
+        Piece::King => Score::new(0, 0),
+    }
+}
+
+pub fn phase_weight(piece: Piece) -> i64 {
+    match piece {
+        Piece::Knight | Piece::Bishop => 1,
+        Piece::Rook => 2,
+        Piece::Queen => 4,
+        _ => 0
+    }
+}
+
+pub fn game_phase(board: &Board) -> i64 {
+    let mut phase = 0;
+    for i in 0..64 {
+        if let Some(p) = board.piece_at(Pos::from_index(i)) {
+            phase += phase_weight(p.piece);
+        }
+    }
+    // Promotions could push this over the max
+    phase.min(MAX_PHASE)
+}
+
+pub fn piece_square(piece: ColoredPiece, pos: Pos) -> Score {
+    // Tables are written from white's point of view, with a8 first
+    let row = match piece.color {
+        Color::White => 7 - pos.row(),
+        Color::Black => pos.row(),
+    };
+    let index = row * 8 + pos.col();
+
+    let (mg, eg) = match piece.piece {
+        Piece::Empty => return Score::zero(),
+        Piece::Pawn => (&MG_PAWN, &EG_PAWN),
+        Piece::Knight => (&MG_KNIGHT, &EG_KNIGHT),
+        Piece::Bishop => (&MG_BISHOP, &EG_BISHOP),
+        Piece::Rook => (&MG_ROOK, &EG_ROOK),
+        Piece::Queen => (&MG_QUEEN, &EG_QUEEN),
+        Piece::King => (&MG_KING, &EG_KING),
+    };
+
+    Score::new(mg[index], eg[index])
+}
+
+// Material and piece-square terms, from white's point of view
+pub fn material(board: &Board) -> Score {
+    let mut score = Score::zero();
+    for i in 0..64 {
+        let pos = Pos::from_index(i);
+        if let Some(p) = board.piece_at(pos) {
+            let s = piece_value(p.piece) + piece_square(p, pos);
+            match p.color {
+                Color::White => score += s,
+                Color::Black => score += -s,
+            }
+        }
+    }
+    score
+}
+
+// Per piece bonus for each attacked square above the baseline, and baseline
+fn mobility_weight(piece: Piece) -> Option<(Score, i64)> {
+    match piece {
+        Piece::Knight => Some((Score::new(4, 4), 4)),
+        Piece::Bishop => Some((Score::new(5, 5), 7)),
+        Piece::Rook => Some((Score::new(2, 4), 7)),
+        Piece::Queen => Some((Score::new(1, 2), 14)),
+        _ => None
+    }
+}
+
+// Attacker units for each square of the enemy king zone a piece attacks
+fn king_attack_weight(piece: Piece) -> Option<i64> {
+    match piece {
+        Piece::Knight | Piece::Bishop => Some(2),
+        Piece::Rook => Some(3),
+        Piece::Queen => Some(5),
+        _ => None
+    }
+}
+
+fn pawn_attacks_of(board: &Board, color: Color) -> BitBoard {
+    let mut attacked = BitBoard::empty();
+    for pos in board.pieces_of(Piece::Pawn, color).iter() {
+        attacked.add_board(pawn_attacks(color, pos));
+    }
+    attacked
+}
+
+// Squares a piece can go to without landing on its own pieces or on squares controlled by enemy pawns, from white's point of view
+pub fn mobility(board: &Board) -> Score {
+    let occupied = board.occupied();
+    let mut score = Score::zero();
+    for &color in &[Color::White, Color::Black] {
+        let excluded = board.pieces(color).with_board(pawn_attacks_of(board, color.inverse()));
+
+        let mut s = Score::zero();
+        for pos in board.pieces(color).iter() {
+            if let Some(piece) = board.piece_at(pos) {
+                if let Some((weight, baseline)) = mobility_weight(piece.piece) {
+                    let squares = attacks_for_piece(occupied, piece, pos).without_board(excluded);
+                    s += weight * (squares.count() as i64 - baseline);
+                }
+            }
+        }
+
+        match color {
+            Color::White => score += s,
+            Color::Black => score += -s,
+        }
+    }
+    score
+}
+
+// Attacks on the squares around the king and holes in its pawn shield, from white's point of view
+pub fn king_safety(board: &Board) -> Score {
+    let occupied = board.occupied();
+    let mut score = Score::zero();
+    for &color in &[Color::White, Color::Black] {
+        let king = match board.king_pos(color) {
+            Some(k) => k,
+            None => continue
+        };
+        let enemy = color.inverse();
+        let zone = attacks_for_piece(occupied, Piece::King.colored(color), king).with(king);
+
+        let mut attackers = 0;
+        let mut units = 0;
+        for pos in board.pieces(enemy).iter() {
+            if let Some(piece) = board.piece_at(pos) {
+                if let Some(weight) = king_attack_weight(piece.piece) {
+                    let hits = attacks_for_piece(occupied, piece, pos).intersection(zone).count() as i64;
+                    if hits > 0 {
+                        attackers += 1;
+                        units += weight * hits;
+                    }
+                }
+            }
+        }
+
+        // A lone attacker is rarely dangerous
+        let danger = if attackers >= 2 {
+            (units * units / 4).min(KING_DANGER_MAX)
+        } else {
+            0
+        };
+        let mut s = Score::new(-danger, -danger / 4);
+
+        if relative_rank(color, king) <= 1 {
+            let own_pawns = board.pieces_of(Piece::Pawn, color);
+            let enemy_pawns = board.pieces_of(Piece::Pawn, enemy);
+
+            let first_col = king.col().saturating_sub(1);
+            let last_col = (king.col() + 1).min(7);
+            for col in first_col..=last_col {
+                let file = BitBoard::empty().with_col(col);
+                let own_on_file = own_pawns.intersection(file);
+
+                let shield = own_on_file.iter()
+                    .map(|p| relative_rank(color, p) as i64 - relative_rank(color, king) as i64)
+                    .filter(|d| *d > 0)
+                    .min();
+                match shield {
+                    Some(1) => (),
+                    Some(2) => s += SHIELD_ADVANCED,
+                    _ => s += SHIELD_MISSING,
+                }
+
+                if own_on_file.is_empty() {
+                    if enemy_pawns.intersection(file).is_empty() {
+                        s += OPEN_FILE_NEAR_KING;
+                    } else {
+                        s += SEMI_OPEN_FILE_NEAR_KING;
+                    }
+                }
+            }
+        }
+
+        match color {
+            Color::White => score += s,
+            Color::Black => score += -s,
+        }
+    }
+    score
+}
+
+
+
+
+// Every evaluation term, from white's point of view
+#[derive(Debug, Clone, Copy)]
+pub struct EvalBreakdown {
+    pub material: Score,
+    pub pawns: Score,
+    pub passed_pawns: Score,
+    pub mobility: Score,
+    pub king_safety: Score,
+    pub phase: i64,
+}
+
+impl EvalBreakdown {
+    pub fn new(board: &Board, pawns: &PawnEntry) -> EvalBreakdown {
+        EvalBreakdown {
+            material: material(board),
+            pawns: pawns.score,
+            passed_pawns: passed_pawns_king_proximity(board, pawns),
+            mobility: mobility(board),
+            king_safety: king_safety(board),
+            phase: game_phase(board),
+        }
+    }
+
+    pub fn total(&self) -> Score {
+        self.material + self.pawns + self.passed_pawns + self.mobility + self.king_safety
+    }
+
+    // Centipawn score from color's point of view
+    pub fn score(&self, color: Color) -> i64 {
+        let score = self.total().taper(self.phase);
+        match color {
+            Color::White => score,
+            Color::Black => -score,
+        }
+    }
+}
+
+impl fmt::Display for EvalBreakdown {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:<14}{:>8}{:>8}{:>8}", "term", "mg", "eg", "total")?;
+        let terms = [
+            ("material", self.material),
+            ("pawns", self.pawns),
+            ("passed pawns", self.passed_pawns),
+            ("mobility", self.mobility),
+            ("king safety", self.king_safety),
+            ("total", self.total()),
+        ];
+        for (name, score) in &terms {
+            writeln!(f, "{:<14}{:>8}{:>8}{:>8}", name, score.mg, score.eg, score.taper(self.phase))?;
+        }
+        write!(f, "phase: {}/{}", self.phase, MAX_PHASE)
+    }
+}
+
+
+// Centipawn score from color's point of view
+pub fn evaluate(board: &Board, color: Color) -> i64 {
+    breakdown(board).score(color)
+}
+
+pub fn breakdown(board: &Board) -> EvalBreakdown {
+    EvalBreakdown::new(board, &evaluate_pawns(board))
+}
+
+
+// Caching evaluator, meant to be kept around by a search for its whole duration
+pub struct Evaluator {
+    pawns: PawnTable,
+}
+
+impl Evaluator {
+    pub fn new() -> Evaluator {
+        Evaluator {
+            pawns: PawnTable::new(),
+        }
+    }
+
+    pub fn evaluate(&mut self, board: &Board, color: Color) -> i64 {
+        self.breakdown(board).score(color)
+    }
+
+    pub fn breakdown(&mut self, board: &Board) -> EvalBreakdown {
+        let pawns = self.pawns.probe(board);
+        EvalBreakdown::new(board, &pawns)
+    }
+
+    pub fn pawn_table(&self) -> &PawnTable {
+        &self.pawns
+    }
+}
+
+
+
+
+#[rustfmt::skip]
+const MG_PAWN: [i64; 64] = [
+      0,   0,   0,   0,   0,   0,  0,   0,
+     98, 134,  61,  95,  68, 126, 34, -11,
+     -6,   7,  26,  31,  65,  56, 25, -20,
+    -14,  13,   6,  21,  23,  12, 17, -23,
+    -27,  -2,  -5,  12,  17,   6, 10, -25,
+    -26,  -4,  -4, -10,   3,   3, 33, -12,
+    -35,  -1, -20, -23, -15,  24, 38, -22,
+      0,   0,   0,   0,   0,   0,  0,   0,
+];
+
+#[rustfmt::skip]
+const EG_PAWN: [i64; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+    178, 173, 158, 134, 147, 132, 165, 187,
+     94, 100,  85,  67,  56,  53,  82,  84,
+     32,  24,  13,   5,  -2,   4,  17,  17,
+     13,   9,  -3,  -7,  -7,  -8,   3,  -1,
+      4,   7,  -6,   1,   0,  -5,  -1,  -8,
+     13,   8,   8,  10,  13,   0,   2,  -7,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const MG_KNIGHT: [i64; 64] = [
+    -167, -89, -34, -49,  61, -97, -15, -107,
+     -73, -41,  72,  36,  23,  62,   7,  -17,
+     -47,  60,  37,  65,  84, 129,  73,   44,
+      -9,  17,  19,  53,  37,  69,  18,   22,
+     -13,   4,  16,  13,  28,  19,  21,   -8,
+     -23,  -9,  12,  10,  19,  17,  25,  -16,
+     -29, -53, -12,  -3,  -1,  18, -14,  -19,
+    -105, -21, -58, -33, -17, -28, -19,  -23,
+];
+
+#[rustfmt::skip]
+const EG_KNIGHT: [i64; 64] = [
+    -58, -38, -13, -28, -31, -27, -63, -99,
+    -25,  -8, -25,  -2,  -9, -25, -24, -52,
+    -24, -20,  10,   9,  -1,  -9, -19, -41,
+    -17,   3,  22,  22,  22,  11,   8, -18,
+    -18,  -6,  16,  25,  16,  17,   4, -18,
+    -23,  -3,  -1,  15,  10,  -3, -20, -22,
+    -42, -20, -10,  -5,  -2, -20, -23, -44,
+    -29, -51, -23, -15, -22, -18, -50, -64,
+];
+
+#[rustfmt::skip]
+const MG_BISHOP: [i64; 64] = [
+    -29,   4, -82, -37, -25, -42,   7,  -8,
+    -26,  16, -18, -13,  30,  59,  18, -47,
+    -16,  37,  43,  40,  35,  50,  37,  -2,
+     -4,   5,  19,  50,  37,  37,   7,  -2,
+     -6,  13,  13,  26,  34,  12,  10,   4,
+      0,  15,  15,  15,  14,  27,  18,  10,
+      4,  15,  16,   0,   7,  21,  33,   1,
+    -33,  -3, -14, -21, -13, -12, -39, -21,
+];
+
+#[rustfmt::skip]
+const EG_BISHOP: [i64; 64] = [
+    -14, -21, -11,  -8, -7,  -9, -17, -24,
+     -8,  -4,   7, -12, -3, -13,  -4, -14,
+      2,  -8,   0,  -1, -2,   6,   0,   4,
+     -3,   9,  12,   9, 14,  10,   3,   2,
+     -6,   3,  13,  19,  7,  10,  -3,  -9,
+    -12,  -3,   8,  10, 13,   3,  -7, -15,
+    -14, -18,  -7,  -1,  4,  -9, -15, -27,
+    -23,  -9, -23,  -5, -9, -16,  -5, -17,
+];
+
+#[rustfmt::skip]
+const MG_ROOK: [i64; 64] = [
+     32,  42,  32,  51, 63,  9,  31,  43,
+     27,  32,  58,  62, 80, 67,  26,  44,
+     -5,  19,  26,  36, 17, 45,  61,  16,
+    -24, -11,   7,  26, 24, 35,  -8, -20,
+    -36, -26, -12,  -1,  9, -7,   6, -23,
+    -45, -25, -16, -17,  3,  0,  -5, -33,
+    -44, -16, -20,  -9, -1, 11,  -6, -71,
+    -19, -13,   1,  17, 16,  7, -37, -26,
+];
+
+#[rustfmt::skip]
+const EG_ROOK: [i64; 64] = [
+    13, 10, 18, 15, 12,  12,   8,   5,
+    11, 13, 13, 11, -3,   3,   8,   3,
+     7,  7,  7,  5,  4,  -3,  -5,  -3,
+     4,  3, 13,  1,  2,   1,  -1,   2,
+     3,  5,  8,  4, -5,  -6,  -8, -11,
+    -4,  0, -5, -1, -7, -12,  -8, -16,
+    -6, -6,  0,  2, -9,  -9, -11,  -3,
+    -9,  2,  3, -1, -5, -13,   4, -20,
+];
+
+#[rustfmt::skip]
+const MG_QUEEN: [i64; 64] = [
+    -28,   0,  29,  12,  59,  44,  43,  45,
+    -24, -39,  -5,   1, -16,  57,  28,  54,
+    -13, -17,   7,   8,  29,  56,  47,  57,
+    -27, -27, -16, -16,  -1,  17,  -2,   1,
+     -9, -26,  -9, -10,  -2,  -4,   3,  -3,
+    -14,   2, -11,  -2,  -5,   2,  14,   5,
+    -35,  -8,  11,   2,   8,  15,  -3,   1,
+     -1, -18,  -9,  10, -15, -25, -31, -50,
+];
+
+#[rustfmt::skip]
+const EG_QUEEN: [i64; 64] = [
+     -9,  22,  22,  27,  27,  19,  10,  20,
+    -17,  20,  32,  41,  58,  25,  30,   0,
+    -20,   6,   9,  49,  47,  35,  19,   9,
+      3,  22,  24,  45,  57,  40,  57,  36,
+    -18,  28,  19,  47,  31,  34,  39,  23,
+    -16, -27,  15,   6,   9,  17,  10,   5,
+    -22, -23, -30, -16, -16, -23, -36, -32,
+    -33, -28, -22, -43,  -5, -32, -20, -41,
+];
+
+#[rustfmt::skip]
+const MG_KING: [i64; 64] = [
+    -65,  23,  16, -15, -56, -34,   2,  13,
+     29,  -1, -20,  -7,  -8,  -4, -38, -29,
+     -9,  24,   2, -16, -20,   6,  22, -22,
+    -17, -20, -12, -27, -30, -25, -14, -36,
+    -49,  -1, -27, -39, -46, -44, -33, -51,
+    -14, -14, -22, -46, -44, -30, -15, -27,
+      1,   7,  -8, -64, -43, -16,   9,   8,
+    -15,  36,  12, -54,   8, -28,  24,  14,
+];
+
+#[rustfmt::skip]
+const EG_KING: [i64; 64] = [
+    -74, -35, -18, -18, -11,  15,   4, -17,
+    -12,  17,  14,  17,  17,  38,  23,  11,
+     10,  17,  23,  15,  20,  45,  44,  13,
+     -8,  22,  24,  27,  26,  33,  26,   3,
+    -18,  -4,  21,  24,  27,  23,   9, -11,
+    -19,  -3,  11,  21,  23,  16,   7,  -9,
+    -27, -11,   4,  13,  14,   4,  -5, -17,
+    -53, -34, -21, -11, -28, -14, -24, -43,
+];
+
+
+
+
+// Board::new mirrors black's back rank across the d/e files, which the tables (mirrored across ranks) don't see as symmetric.
+// This is the same position with black's king and queen facing white's.
+#[cfg(test)]
+fn facing_start() -> Board {
+    let mut board = Board::new();
+    board.set_piece(Pos::new(3, 7), Piece::Queen.colored(Color::Black));
+    board.set_piece(Pos::new(4, 7), Piece::King.colored(Color::Black));
+    board
+}
+
+#[test]
+fn eval_start_is_balanced() {
+    let board = facing_start();
+    assert_eq!(game_phase(&board), MAX_PHASE);
+    assert_eq!(evaluate(&board, Color::White), 0);
+    assert_eq!(evaluate(&board, Color::Black), 0);
+}
+
+#[test]
+fn eval_is_symmetric() {
+    let board = facing_start().moved(Move(Pos::new(6, 0), Pos::new(5, 2)));
+    let mirrored = facing_start().moved(Move(Pos::new(6, 7), Pos::new(5, 5)));
+    assert!(evaluate(&board, Color::White) > 0);
+    assert_eq!(evaluate(&board, Color::White), evaluate(&mirrored, Color::Black));
+}
+
+#[test]
+fn eval_king_safety() {
+    let mut board = Board::empty();
+    board.set_piece(Pos::new(6, 0), Piece::King.colored(Color::White));
+    board.set_piece(Pos::new(4, 7), Piece::King.colored(Color::Black));
+    for col in 5..8 {
+        board.set_piece(Pos::new(col, 1), Piece::Pawn.colored(Color::White));
+    }
+    let sheltered = king_safety(&board);
+
+    board.clear_piece(Pos::new(6, 1));
+    assert!(king_safety(&board).mg < sheltered.mg);
+
+    board.set_piece(Pos::new(6, 4), Piece::Queen.colored(Color::Black));
+    board.set_piece(Pos::new(5, 3), Piece::Knight.colored(Color::Black));
+    let attacked = breakdown(&board);
+    assert!(attacked.king_safety.mg < king_safety(&board.moved(Move(Pos::new(5, 3), Pos::new(0, 5)))).mg);
+    assert_eq!(attacked.score(Color::White), -attacked.score(Color::Black));
+}
+
+#[test]
+fn eval_mobility() {
+    let mut board = Board::empty();
+    board.set_piece(Pos::new(3, 3), Piece::Knight.colored(Color::White));
+    board.set_piece(Pos::new(0, 7), Piece::Knight.colored(Color::Black));
+    assert!(mobility(&board).mg > 0);
+}
+
+#[test]
+fn eval_tapers_between_phases() {
+    let s = Score::new(100, 300);
+    assert_eq!(s.taper(MAX_PHASE), 100);
+    assert_eq!(s.taper(0), 300);
+    assert_eq!(s.taper(MAX_PHASE / 2), 200);
+}
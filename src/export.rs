@@ -0,0 +1,161 @@
+use crate::tournament::*;
+use crate::piece::*;
+
+use std::fmt::Write;
+
+// Machine readable tournament output.
+// JSON is written by hand, the records are flat enough that pulling in a serializer isn't worth it.
+
+const CSV_HEADER: &str = "game,white,black,seed,result,winner,termination,moves,duration_ms";
+
+
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+// PGN style result from white's point of view
+pub fn result_string(result: &GameResult) -> &'static str {
+    match result.winner_color() {
+        Some(Color::White) => "1-0",
+        Some(Color::Black) => "0-1",
+        None => "1/2-1/2",
+    }
+}
+
+fn winner_name<'a>(result: &GameResult, names: &'a [String]) -> Option<&'a str> {
+    result.winner.map(|w| {
+        let player = if w == 0 { result.players.0 } else { result.players.1 };
+        names[player].as_str()
+    })
+}
+
+
+pub fn game_json(index: usize, result: &GameResult, names: &[String]) -> String {
+    format!(
+        "{{\"game\":{},\"white\":{},\"black\":{},\"seed\":{},\"result\":\"{}\",\"winner\":{},\"termination\":\"{}\",\"moves\":{},\"duration_ms\":{:.3}}}",
+        index,
+        json_string(&names[result.white_player()]),
+        json_string(&names[result.black_player()]),
+        result.seed,
+        result_string(result),
+        winner_name(result, names).map(json_string).unwrap_or_else(|| "null".to_string()),
+        result.termination.name(),
+        result.moves,
+        result.duration.as_secs_f64() * 1000.0,
+    )
+}
+
+// One JSON object per line
+pub fn games_jsonl(results: &[GameResult], names: &[String]) -> String {
+    let mut out = String::new();
+    for (i, r) in results.iter().enumerate() {
+        writeln!(out, "{}", game_json(i, r, names)).unwrap();
+    }
+    out
+}
+
+pub fn games_csv(results: &[GameResult], names: &[String]) -> String {
+    let mut out = String::new();
+    writeln!(out, "{}", CSV_HEADER).unwrap();
+    for (i, r) in results.iter().enumerate() {
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{},{},{:.3}",
+            i,
+            csv_field(&names[r.white_player()]),
+            csv_field(&names[r.black_player()]),
+            r.seed,
+            result_string(r),
+            winner_name(r, names).map(csv_field).unwrap_or_default(),
+            r.termination.name(),
+            r.moves,
+            r.duration.as_secs_f64() * 1000.0,
+        )
+        .unwrap();
+    }
+    out
+}
+
+// Final standings, best first in the tournament's rating system
+pub fn standings_json(tournament: &Tournament) -> String {
+    let mut out = String::new();
+    writeln!(out, "{{").unwrap();
+    writeln!(out, "  \"seed\": {},", tournament.seed()).unwrap();
+    writeln!(out, "  \"rating_system\": \"{:?}\",", tournament.rating_system()).unwrap();
+    writeln!(out, "  \"standings\": [").unwrap();
+
+    let standings = tournament.standings();
+    for (rank, &i) in standings.iter().enumerate() {
+        let player = &tournament.players[i];
+        let glicko = tournament.glicko(i);
+        write!(
+            out,
+            "    {{\"rank\":{},\"name\":{},\"elo\":{:.1},\"glicko2\":{{\"rating\":{:.1},\"deviation\":{:.1},\"volatility\":{:.6}}},\"wins\":{},\"losses\":{},\"draws\":{}}}",
+            rank + 1,
+            json_string(&player.name()),
+            player.elo_score(),
+            glicko.rating,
+            glicko.deviation,
+            glicko.volatility,
+            player.victories,
+            player.loses,
+            player.draws,
+        )
+        .unwrap();
+        writeln!(out, "{}", if rank + 1 < standings.len() { "," } else { "" }).unwrap();
+    }
+
+    writeln!(out, "  ]").unwrap();
+    writeln!(out, "}}").unwrap();
+    out
+}
+
+
+
+#[test]
+fn export_game_records() {
+    use crate::elo::Termination;
+    use std::time::Duration;
+
+    let names = vec!["Swarm".to_string(), "Odd \"name\", really".to_string()];
+    let result = GameResult {
+        players: (1, 0),
+        winner: Some(0),
+        white: 1,
+        moves: 42,
+        seed: 7,
+        termination: Termination::KingCaptured,
+        duration: Duration::from_millis(3),
+        opening: None,
+    };
+
+    assert_eq!(
+        game_json(0, &result, &names),
+        "{\"game\":0,\"white\":\"Swarm\",\"black\":\"Odd \\\"name\\\", really\",\"seed\":7,\"result\":\"0-1\",\"winner\":\"Odd \\\"name\\\", really\",\"termination\":\"king captured\",\"moves\":42,\"duration_ms\":3.000}"
+    );
+
+    let csv = games_csv(&[result], &names);
+    assert_eq!(csv.lines().nth(1), Some("0,Swarm,\"Odd \"\"name\"\", really\",7,0-1,\"Odd \"\"name\"\", really\",king captured,42,3.000"));
+}
@@ -0,0 +1,206 @@
+use std::f64::consts::PI;
+
+// Glicko-2, following http://www.glicko.net/glicko/glicko2.pdf
+// Results are accumulated during a rating period and every player is updated at once when it ends.
+
+const SCALE: f64 = 173.7178;
+
+pub const DEFAULT_RATING: f64 = 1500.0;
+pub const DEFAULT_DEVIATION: f64 = 350.0;
+pub const DEFAULT_VOLATILITY: f64 = 0.06;
+// Constrains the change in volatility over time, reasonable values are between 0.3 and 1.2
+pub const DEFAULT_TAU: f64 = 0.5;
+
+const CONVERGENCE_TOLERANCE: f64 = 1e-6;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Glicko2Rating {
+    pub rating: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+}
+
+impl Glicko2Rating {
+    pub fn new() -> Glicko2Rating {
+        Glicko2Rating {
+            rating: DEFAULT_RATING,
+            deviation: DEFAULT_DEVIATION,
+            volatility: DEFAULT_VOLATILITY,
+        }
+    }
+
+    fn mu(&self) -> f64 {
+        (self.rating - DEFAULT_RATING) / SCALE
+    }
+
+    fn phi(&self) -> f64 {
+        self.deviation / SCALE
+    }
+
+    // Half width of the 95% confidence interval
+    pub fn error_bar(&self) -> f64 {
+        1.96 * self.deviation
+    }
+}
+
+
+pub struct Glicko2 {
+    players: Vec<Glicko2Rating>,
+    // Opponent and score (1 for a win, 0.5 for a draw) of every game in the current period
+    pending: Vec<Vec<(usize, f64)>>,
+    tau: f64,
+    period_length: usize,
+    games_in_period: usize,
+}
+
+impl Glicko2 {
+    pub fn new(player_count: usize, period_length: usize) -> Glicko2 {
+        Glicko2 {
+            players: vec![Glicko2Rating::new(); player_count],
+            pending: vec![Vec::new(); player_count],
+            tau: DEFAULT_TAU,
+            period_length: period_length.max(1),
+            games_in_period: 0,
+        }
+    }
+
+    pub fn set_tau(&mut self, tau: f64) {
+        self.tau = tau;
+    }
+
+    pub fn rating(&self, player: usize) -> Glicko2Rating {
+        self.players[player]
+    }
+
+    pub fn set_rating(&mut self, player: usize, rating: Glicko2Rating) {
+        self.players[player] = rating;
+    }
+
+    // score is from a's point of view. The period ends automatically after period_length games.
+    pub fn add_game(&mut self, a: usize, b: usize, score: f64) {
+        self.pending[a].push((b, score));
+        self.pending[b].push((a, 1.0 - score));
+        self.games_in_period += 1;
+        if self.games_in_period >= self.period_length {
+            self.end_period();
+        }
+    }
+
+    pub fn end_period(&mut self) {
+        let previous = self.players.clone();
+        for (player, games) in self.pending.iter_mut().enumerate() {
+            self.players[player] = update(previous[player], &previous, games, self.tau);
+            games.clear();
+        }
+        self.games_in_period = 0;
+    }
+
+    pub fn has_pending_games(&self) -> bool {
+        self.games_in_period > 0
+    }
+}
+
+
+fn g(phi: f64) -> f64 {
+    1.0 / (1.0 + 3.0 * phi * phi / (PI * PI)).sqrt()
+}
+
+fn expected(mu: f64, mu_j: f64, phi_j: f64) -> f64 {
+    1.0 / (1.0 + (-g(phi_j) * (mu - mu_j)).exp())
+}
+
+fn update(player: Glicko2Rating, ratings: &[Glicko2Rating], games: &[(usize, f64)], tau: f64) -> Glicko2Rating {
+    let (mu, phi, sigma) = (player.mu(), player.phi(), player.volatility);
+
+    if games.is_empty() {
+        let phi = (phi * phi + sigma * sigma).sqrt();
+        return Glicko2Rating {
+            deviation: (phi * SCALE).min(DEFAULT_DEVIATION),
+            ..player
+        };
+    }
+
+    let mut inv_v = 0.0;
+    let mut sum = 0.0;
+    for &(opponent, score) in games {
+        let (mu_j, phi_j) = (ratings[opponent].mu(), ratings[opponent].phi());
+        let e = expected(mu, mu_j, phi_j);
+        inv_v += g(phi_j) * g(phi_j) * e * (1.0 - e);
+        sum += g(phi_j) * (score - e);
+    }
+    let v = 1.0 / inv_v;
+    let delta = v * sum;
+
+    let sigma = new_volatility(phi, sigma, v, delta, tau);
+
+    let phi_star = (phi * phi + sigma * sigma).sqrt();
+    let new_phi = 1.0 / (1.0 / (phi_star * phi_star) + 1.0 / v).sqrt();
+    let new_mu = mu + new_phi * new_phi * sum;
+
+    Glicko2Rating {
+        rating: new_mu * SCALE + DEFAULT_RATING,
+        deviation: new_phi * SCALE,
+        volatility: sigma,
+    }
+}
+
+// Illinois algorithm, step 5 of the paper
+fn new_volatility(phi: f64, sigma: f64, v: f64, delta: f64, tau: f64) -> f64 {
+    let a = (sigma * sigma).ln();
+    let f = |x: f64| {
+        let ex = x.exp();
+        let d = phi * phi + v + ex;
+        ex * (delta * delta - phi * phi - v - ex) / (2.0 * d * d) - (x - a) / (tau * tau)
+    };
+
+    let mut big_a = a;
+    let mut big_b = if delta * delta > phi * phi + v {
+        (delta * delta - phi * phi - v).ln()
+    } else {
+        let mut k = 1.0;
+        while f(a - k * tau) < 0.0 {
+            k += 1.0;
+        }
+        a - k * tau
+    };
+
+    let mut f_a = f(big_a);
+    let mut f_b = f(big_b);
+    while (big_b - big_a).abs() > CONVERGENCE_TOLERANCE {
+        let big_c = big_a + (big_a - big_b) * f_a / (f_b - f_a);
+        let f_c = f(big_c);
+        if f_c * f_b <= 0.0 {
+            big_a = big_b;
+            f_a = f_b;
+        } else {
+            f_a /= 2.0;
+        }
+        big_b = big_c;
+        f_b = f_c;
+    }
+
+    (big_a / 2.0).exp()
+}
+
+
+
+#[test]
+fn glicko2_paper_example() {
+    let mut glicko = Glicko2::new(4, 3);
+    glicko.set_rating(0, Glicko2Rating { rating: 1500.0, deviation: 200.0, volatility: 0.06 });
+    glicko.set_rating(1, Glicko2Rating { rating: 1400.0, deviation: 30.0, volatility: 0.06 });
+    glicko.set_rating(2, Glicko2Rating { rating: 1550.0, deviation: 100.0, volatility: 0.06 });
+    glicko.set_rating(3, Glicko2Rating { rating: 1700.0, deviation: 300.0, volatility: 0.06 });
+
+    glicko.add_game(0, 1, 1.0);
+    glicko.add_game(0, 2, 0.0);
+    assert!(glicko.has_pending_games());
+    glicko.add_game(0, 3, 0.0);
+    assert!(!glicko.has_pending_games());
+
+    let r = glicko.rating(0);
+    assert!((r.rating - 1464.06).abs() < 0.01);
+    assert!((r.deviation - 151.52).abs() < 0.01);
+    assert!((r.volatility - 0.05999).abs() < 1e-5);
+}
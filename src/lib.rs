@@ -0,0 +1,36 @@
+#![allow(dead_code)]
+// The library only exists so integration tests can reach the controllers, it keeps the binary's API
+#![allow(clippy::new_without_default, clippy::result_unit_err, clippy::len_without_is_empty)]
+// Style of the original board and move generation code
+#![allow(clippy::write_with_newline, clippy::assign_op_pattern, clippy::nonminimal_bool, clippy::redundant_field_names, clippy::manual_clamp, clippy::manual_range_contains, clippy::single_match)]
+
+extern crate rand;
+extern crate indicatif;
+
+pub mod elo;
+pub mod pos;
+pub mod board;
+pub mod bitboard;
+pub mod piece;
+pub mod moves;
+pub mod player;
+pub mod ai;
+pub mod search;
+pub mod eval;
+pub mod pawns;
+pub mod zobrist;
+pub mod mcts;
+pub mod tt;
+pub mod tournament;
+pub mod rating;
+pub mod glicko;
+pub mod sprt;
+pub mod schedule;
+pub mod crosstable;
+pub mod export;
+pub mod database;
+pub mod opening;
+pub mod adjudication;
+pub mod clock;
+pub mod uci;
+pub mod registry;
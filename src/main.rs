@@ -1,31 +1,33 @@
-#![allow(dead_code)]
-
 extern crate rand;
-extern crate indicatif;
-
-mod elo;
-mod pos;
-mod board;
-mod bitboard;
-mod piece;
-mod moves;
-mod player;
-mod ai;
+extern crate chess_rs;
 
-use elo::*;
-use ai::*;
+use chess_rs::elo::*;
+use chess_rs::ai::*;
+use chess_rs::tournament::*;
+use chess_rs::rating::*;
+use chess_rs::sprt::*;
+use chess_rs::schedule::*;
+use chess_rs::crosstable::*;
+use chess_rs::export::*;
+use chess_rs::database::*;
+use chess_rs::opening::*;
+use chess_rs::adjudication::*;
+use chess_rs::clock::*;
+use chess_rs::registry::*;
 
 use std::time::{Instant, Duration};
-use std::cmp;
 
-use rand::{thread_rng, Rng};
-use indicatif::ProgressIterator;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
 
+// Approximate number of games, the exact count depends on the format
 const GAMES: usize = 50000;
+const SWISS_ROUNDS: usize = 4;
+// Player whose maximum likelihood rating is fixed, so ratings stay comparable between runs
+const ANCHOR: &str = "Random";
 
-fn gen_player_indexes(player_count: usize) -> (usize, usize) {
+fn gen_player_indexes<R: Rng>(player_count: usize, rng: &mut R) -> (usize, usize) {
     assert!(player_count > 1);
-    let mut rng = thread_rng();
     let i = rng.gen_range(0, player_count);
     let j = rng.gen_range(0, player_count - 1);
 
@@ -40,43 +42,244 @@ fn per_second(n: usize, time: Duration) -> f64 {
     (n as f64 / time.as_millis() as f64) * 1000.0
 }
 
+fn arg_value(name: &str) -> Option<String> {
+    let args = std::env::args().collect::<Vec<_>>();
+    args.iter()
+        .position(|a| a == name)
+        .and_then(|i| args.get(i + 1))
+        .cloned()
+}
+
+// Every value of an option that can be repeated
+fn arg_values(name: &str) -> Vec<String> {
+    let args = std::env::args().collect::<Vec<_>>();
+    args.windows(2)
+        .filter(|w| w[0] == name)
+        .map(|w| w[1].clone())
+        .collect()
+}
+
+fn has_flag(name: &str) -> bool {
+    std::env::args().any(|a| a == name)
+}
+
+// --seed <u64> replays a previous tournament
+fn parse_seed() -> Option<u64> {
+    arg_value("--seed").map(|s| s.parse().expect("Invalid seed"))
+}
+
+// --rating elo|glicko2 selects the system used for the standings
+fn parse_rating_system() -> Option<RatingSystem> {
+    arg_value("--rating").map(|s| match s.to_lowercase().as_str() {
+        "elo" => RatingSystem::Elo,
+        "glicko" | "glicko2" => RatingSystem::Glicko2,
+        _ => panic!("Unknown rating system {}", s),
+    })
+}
+
+// Checks whether safe captures improve on plain captures.
+// Random fallbacks keep games varied, deterministic controllers would only replay the same pair of games.
+fn run_sprt(seed: u64) {
+    let candidate = EloPlayer::new(|| CaptureAI::new_safe_with_fallback(RandomAI::new(true)));
+    let baseline = EloPlayer::new(|| CaptureAI::new_with_fallback(RandomAI::new(true)));
+    let sprt = Sprt::new(0.0, 20.0);
+
+    println!("SPRT {} vs {} (elo0: {}, elo1: {}, seed: {})", candidate.name(), baseline.name(), sprt.elo0, sprt.elo1, seed);
+    println!("bounds: [{:.2}, {:.2}]", sprt.lower_bound(), sprt.upper_bound());
+    let report = sprt.run(&candidate, &baseline, seed, |r| {
+        if r.stats.pair_count() % 100 == 0 {
+            println!("{}", r);
+        }
+    });
+    println!("{}", report);
+}
+
 fn main() {
-    let mut players = Vec::new();
-    players.push(EloPlayer::new(RandomAI::new(true)));
-    players.push(EloPlayer::new(FirstMoveAI::new()));
-    players.push(EloPlayer::new(SwarmAI::new()));
-    players.push(EloPlayer::new(CaptureAI::new()));
+    if has_flag("--sprt") {
+        run_sprt(parse_seed().unwrap_or_else(rand::random));
+        return;
+    }
 
-    let start = Instant::now();
+    let registry = Registry::standard();
+    if has_flag("--controllers") {
+        for signature in registry.signatures() {
+            println!("{}", signature);
+        }
+        return;
+    }
 
-    println!("Simulating:");
+    // --player <spec> (repeated) replaces the default participants, e.g. --player "Capture(Random)" --player "AlphaBeta(depth=3)"
+    let specs = arg_values("--player");
+    // Every spec is checked before any engine is started
+    for spec in &specs {
+        if let Err(e) = parse_spec(spec).and_then(|s| registry.check(&s)) {
+            panic!("Invalid player {}: {}", spec, e);
+        }
+    }
+    let mut players = if specs.is_empty() {
+        vec![
+            EloPlayer::new(|| RandomAI::new(true)),
+            EloPlayer::new(FirstMoveAI::new),
+            EloPlayer::new(SwarmAI::new),
+            EloPlayer::new(CaptureAI::new),
+            EloPlayer::new(CaptureAI::new_safe),
+        ]
+    } else {
+        specs.iter().map(|s| registry.player(s).unwrap_or_else(|e| panic!("Invalid player {}: {}", s, e))).collect()
+    };
 
-    let mut moves = 0;
-    for _ in (0..(GAMES / 1000)).progress() {
-        for _ in 0..1000 {
-            let (a, b) = gen_player_indexes(players.len());
-            let (first, second) = (cmp::min(a, b), cmp::max(a, b));
-            assert!(a != b);
+    // --uci <engine> adds an external UCI engine, every game starts its own process
+    if let Some(engine) = arg_value("--uci") {
+        let spec = ControllerSpec { name: "Uci".to_string(), args: vec![(None, ControllerSpec::new(&engine))] };
+        players.push(registry.player(&spec.to_string()).unwrap_or_else(|e| panic!("{}", e)));
+    }
+    assert!(players.len() >= 2, "A tournament needs at least two players");
+
+    let mut tournament = Tournament::new(players);
+    if let Some(seed) = parse_seed() {
+        tournament.set_seed(seed);
+    }
+    if let Some(rating_system) = parse_rating_system() {
+        tournament.set_rating_system(rating_system);
+    }
 
-            let (pa, pb) = players.split_at_mut(second);
-            let pa: &mut EloPlayer = &mut pa[first];
-            let pb: &mut EloPlayer = &mut pb[0];
-            moves += pa.play_once(pb);
+    // --openings <file> plays every pairing twice from each position of an EPD/FEN or PGN file
+    assert!(!(has_flag("--openings") && has_flag("--random-plies")), "--openings and --random-plies can't both be given");
+    if let Some(path) = arg_value("--openings") {
+        let openings = OpeningSuite::load(&path).expect("Unable to load openings");
+        println!("{} openings loaded from {}", openings.len(), path);
+        tournament.set_openings(openings);
+    }
+
+    // --random-plies <n> starts every pair of games from n random moves instead
+    if let Some(plies) = arg_value("--random-plies") {
+        tournament.set_random_openings(plies.parse().expect("Invalid ply count"));
+    }
+
+    // --adjudicate ends games early once the reference evaluation or material is decisive
+    if has_flag("--adjudicate") {
+        tournament.set_adjudication(Adjudication::standard());
+    }
+
+    // --tc <control> plays with clocks, e.g. 60, 10+0.1, 40/60 or st=0.05 (seconds), --tolerance <ms> delays flag falls
+    if let Some(tc) = arg_value("--tc") {
+        let time_control = tc.parse::<TimeControl>().unwrap_or_else(|_| panic!("Invalid time control {}", tc));
+        let tolerance = arg_value("--tolerance").map(|t| Duration::from_millis(t.parse().expect("Invalid tolerance"))).unwrap_or(DEFAULT_TIME_TOLERANCE);
+        tournament.set_time_control(Some(time_control), tolerance);
+    }
+
+    // --db <dir> continues from the ratings of previous runs and stores this one's games
+    let mut db = arg_value("--db").map(|dir| RatingsDb::open(dir).expect("Unable to open ratings database"));
+    if let Some(db) = &mut db {
+        // --recompute rebuilds the stored ratings from the full history first
+        if has_flag("--recompute") {
+            db.recompute().expect("Unable to recompute ratings");
         }
+        RatingsDb::check_players(&tournament.players).unwrap_or_else(|e| panic!("Unable to use ratings database: {}", e));
+        db.load_ratings(&mut tournament.players);
     }
+    let previous_games = tournament.players.iter().map(|p| p.games()).sum::<usize>();
+
+    let player_count = tournament.players.len();
+    let format = arg_value("--format").unwrap_or_else(|| "round-robin".to_string());
+
+    let start = Instant::now();
+
+    println!("Simulating {} (seed: {}):", format, tournament.seed());
+
+    let mut swiss = None;
+    let results = match format.as_str() {
+        "round-robin" => tournament.run(&mut RoundRobin::new(player_count, GAMES / (player_count * (player_count - 1) / 2))),
+        // The last player is the challenger
+        "gauntlet" => tournament.run(&mut Gauntlet::new(player_count, player_count - 1, GAMES / (player_count - 1))),
+        "swiss" => {
+            let rounds = arg_value("--rounds").map(|s| s.parse().expect("Invalid round count")).unwrap_or(SWISS_ROUNDS);
+            tournament.run(swiss.insert(Swiss::new(player_count, rounds)))
+        }
+        "random" => {
+            let mut rng = StdRng::seed_from_u64(tournament.seed());
+            let pairings = (0..GAMES).map(|_| gen_player_indexes(player_count, &mut rng)).collect::<Vec<_>>();
+            tournament.play(&pairings)
+        }
+        _ => panic!("Unknown format {}", format),
+    };
+    let games = results.len();
+    let moves = results.iter().map(|r| r.moves).sum::<usize>();
 
     let end = Instant::now();
     let time = end.duration_since(start);
 
-    println!("\n{} games played in {:?} ({} g/s)", GAMES, time, per_second(GAMES, time).round() as i64);
+    println!("\n{} games played in {:?} ({} g/s)", games, time, per_second(games, time).round() as i64);
     println!("{} moves played ({} m/s)", moves, per_second(moves, time).round() as i64);
 
+    let mut terminations: Vec<(&str, usize)> = Vec::new();
+    for r in &results {
+        match terminations.iter_mut().find(|(name, _)| *name == r.termination.name()) {
+            Some((_, count)) => *count += 1,
+            None => terminations.push((r.termination.name(), 1)),
+        }
+    }
+    for (name, count) in terminations {
+        println!("  {}: {}", name, count);
+    }
+
+    let names = tournament.players.iter().map(|p| p.name()).collect::<Vec<_>>();
+
+    let anchor = names.iter().position(|n| n == ANCHOR).unwrap_or_else(|| {
+        println!("{} isn't playing, {} is anchored instead", ANCHOR, names[0]);
+        0
+    });
+    let matrix = ResultMatrix::from_results(tournament.players.len(), &results);
+    let fit = MaxLikelihood::new(anchor, ELO_STARTING_SCORE as f64).fit(&matrix);
+    println!("white advantage: {}, draw elo: {}", fit.white_advantage.round() as i64, fit.draw_elo.round() as i64);
+
+    println!("standings by {:?}:", tournament.rating_system());
     let mut total = 0;
-    for player in players {
+    for i in tournament.standings() {
+        let player = &tournament.players[i];
+        let glicko = tournament.glicko(i);
         println!("\n{}", player.name());
         println!("  elo: {}", player.elo_score().round() as i64);
+        println!("  glicko2: {} ± {} (volatility: {:.4})", glicko.rating.round() as i64, glicko.error_bar().round() as i64, glicko.volatility);
+        println!("  ml elo: {} ± {}", fit.ratings[i].round() as i64, fit.error_bar(i).round() as i64);
         println!("  games: (w: {}, l: {}, d: {})", player.victories, player.loses, player.draws);
-        total += player.victories + player.loses + player.draws;
+        total += player.games();
+    }
+    assert!(total == previous_games + games * 2);
+
+    if let Some(swiss) = swiss {
+        println!("\nSwiss standings:");
+        for s in swiss.standings() {
+            println!("  {:<20} {:>5} (buchholz: {})", names[s.player], s.score as f64 / 2.0, s.buchholz as f64 / 2.0);
+        }
+    }
+
+    let crosstable = Crosstable::from_results(names.clone(), &results);
+    println!("\nCrosstable:");
+    println!("{}", crosstable);
+    print!("{}", crosstable.details());
+    // --crosstable <path> saves it as csv
+    if let Some(path) = arg_value("--crosstable") {
+        std::fs::write(&path, crosstable.to_csv()).expect("Unable to write crosstable");
+    }
+
+    // --export <dir> saves the games and standings for other tools
+    if let Some(dir) = arg_value("--export") {
+        let dir = std::path::Path::new(&dir);
+        std::fs::create_dir_all(dir).expect("Unable to create export directory");
+        std::fs::write(dir.join("games.jsonl"), games_jsonl(&results, &names)).expect("Unable to write games");
+        std::fs::write(dir.join("games.csv"), games_csv(&results, &names)).expect("Unable to write games");
+        std::fs::write(dir.join("standings.json"), standings_json(&tournament)).expect("Unable to write standings");
     }
-    assert!(total == GAMES * 2);
+
+    if let Some(db) = &mut db {
+        db.record(&results, &tournament.players).expect("Unable to store results");
+        println!("\nRatings database:");
+        for e in db.entries() {
+            println!("  {:<24} elo: {:>5} games: (w: {}, l: {}, d: {})", e.key.to_string(), e.elo.score().round() as i64, e.wins, e.losses, e.draws);
+        }
+    }
+
+    println!("\nLikelihood of superiority:");
+    println!("{}", fit.los_table(&names));
 }
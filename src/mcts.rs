@@ -0,0 +1,312 @@
+use crate::board::*;
+use crate::piece::*;
+use crate::pos::*;
+use crate::player::*;
+use crate::ai::*;
+use crate::clock::*;
+use crate::elo::*;
+
+use std::cell::RefCell;
+use std::time::{Instant, Duration};
+
+const DEFAULT_EXPLORATION: f64 = 1.41;
+const MAX_ROLLOUT_PLIES: usize = 100;
+
+
+#[derive(Debug, Clone, Copy)]
+pub enum Budget {
+    Iterations(usize),
+    Time(Duration),
+}
+
+
+struct Node {
+    board: Board,
+    // Color to play in this node
+    color: Color,
+    // Move that led to this node
+    mv: Option<Move>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    untried: Vec<Move>,
+
+    visits: u32,
+    // Sum of results from the point of view of the player that moved into this node
+    wins: f64,
+}
+
+impl Node {
+    fn new(board: Board, color: Color, mv: Option<Move>, parent: Option<usize>) -> Node {
+        let untried = if board.has_king(color) {
+            board.possible_moves(color).collect()
+        } else {
+            Vec::new()
+        };
+
+        Node {
+            board,
+            color,
+            mv,
+            parent,
+            children: Vec::new(),
+            untried,
+            visits: 0,
+            wins: 0.0,
+        }
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.untried.is_empty() && self.children.is_empty()
+    }
+
+    fn uct(&self, parent_visits: u32, exploration: f64) -> f64 {
+        let visits = self.visits as f64;
+        self.wins / visits + exploration * ((parent_visits as f64).ln() / visits).sqrt()
+    }
+}
+
+
+struct Tree {
+    nodes: Vec<Node>,
+}
+
+impl Tree {
+    fn new(board: &Board, color: Color) -> Tree {
+        Tree {
+            nodes: vec![Node::new(board.clone(), color, None, None)],
+        }
+    }
+
+    fn root(&self) -> &Node {
+        &self.nodes[0]
+    }
+
+    fn select(&self, exploration: f64) -> usize {
+        let mut index = 0;
+        loop {
+            let node = &self.nodes[index];
+            if !node.untried.is_empty() || node.children.is_empty() {
+                return index;
+            }
+            index = *node.children.iter()
+                .max_by(|a, b| {
+                    let a = self.nodes[**a].uct(node.visits, exploration);
+                    let b = self.nodes[**b].uct(node.visits, exploration);
+                    a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+                })
+                .unwrap();
+        }
+    }
+
+    fn expand(&mut self, index: usize) -> usize {
+        match self.nodes[index].untried.pop() {
+            Some(m) => {
+                let node = &self.nodes[index];
+                let child = Node::new(node.board.moved(m), node.color.inverse(), Some(m), Some(index));
+                let child_index = self.nodes.len();
+                self.nodes.push(child);
+                self.nodes[index].children.push(child_index);
+                child_index
+            }
+            None => index
+        }
+    }
+
+    fn backpropagate(&mut self, mut index: usize, winner: Option<Color>) {
+        loop {
+            let node = &mut self.nodes[index];
+            node.visits += 1;
+            node.wins += match winner {
+                Some(c) if c == node.color => 0.0,
+                Some(_) => 1.0,
+                None => 0.5,
+            };
+            match node.parent {
+                Some(p) => index = p,
+                None => break
+            }
+        }
+    }
+
+    fn best_move(&self) -> Option<Move> {
+        // Statistics are too flat to notice wins when every playout ends up winning anyway
+        let children = &self.root().children;
+        let decisive = children.iter().find(|c| self.nodes[**c].is_terminal());
+        decisive.or_else(|| children.iter().max_by_key(|c| self.nodes[**c].visits))
+            .and_then(|c| self.nodes[*c].mv)
+    }
+
+    // Keeps the subtree of the node matching board, if it was explored within two plies of the root
+    fn reroot(self, board: &Board, color: Color) -> Option<Tree> {
+        let hash = board.hash();
+        let mut candidates = vec![0];
+        for c in &self.root().children {
+            candidates.extend(self.nodes[*c].children.iter().copied());
+        }
+
+        let new_root = candidates.into_iter()
+            .find(|i| self.nodes[*i].color == color && self.nodes[*i].board.hash() == hash)?;
+
+        let mut old_nodes = self.nodes.into_iter().map(Some).collect::<Vec<_>>();
+        let mut nodes = Vec::new();
+        let mut queue = vec![(new_root, None)];
+        while let Some((old, parent)) = queue.pop() {
+            let mut node = old_nodes[old].take().unwrap();
+            let index = nodes.len();
+            node.parent = parent;
+            if let Some(p) = parent {
+                let parent: &mut Node = &mut nodes[p];
+                parent.children.push(index);
+            }
+            queue.extend(node.children.drain(..).map(|c| (c, Some(index))));
+            nodes.push(node);
+        }
+
+        nodes[0].mv = None;
+        Some(Tree {
+            nodes,
+        })
+    }
+}
+
+
+
+
+pub struct MctsAI {
+    budget: Budget,
+    exploration: f64,
+    rollout: Box<dyn PlayerController>,
+    tree: RefCell<Option<Tree>>,
+}
+
+impl MctsAI {
+    pub fn new(iterations: usize) -> MctsAI {
+        MctsAI::new_with_rollout(Budget::Iterations(iterations), RandomAI::new(true))
+    }
+
+    pub fn new_with_rollout<T: 'static + PlayerController>(budget: Budget, rollout: T) -> MctsAI {
+        MctsAI {
+            budget,
+            exploration: DEFAULT_EXPLORATION,
+            rollout: Box::new(rollout),
+            tree: RefCell::new(None),
+        }
+    }
+
+    fn simulate(&self, board: &Board, mut color: Color) -> Option<Color> {
+        let mut board = board.clone();
+        for _ in 0..MAX_ROLLOUT_PLIES {
+            if !board.has_king(color) {
+                return Some(color.inverse());
+            }
+            match self.rollout.play(color, &board).as_move().map(|m| board.try_move(m)) {
+                Some(Ok(b)) => board = b,
+                _ => return Some(color.inverse())
+            }
+            color = color.inverse();
+        }
+        None
+    }
+
+    // Stops at the budget or the deadline, whichever comes first, but always after at least one iteration
+    fn search(&self, tree: &mut Tree, deadline: Option<Instant>) {
+        let start = Instant::now();
+        let mut iterations = 0;
+        loop {
+            let done = match self.budget {
+                Budget::Iterations(n) => iterations >= n,
+                Budget::Time(t) => start.elapsed() >= t,
+            } || (iterations > 0 && deadline.map(|d| Instant::now() >= d).unwrap_or(false));
+            if done {
+                break;
+            }
+            iterations += 1;
+
+            let leaf = tree.select(self.exploration);
+            let node = tree.expand(leaf);
+
+            let winner = {
+                let node = &tree.nodes[node];
+                if node.is_terminal() {
+                    Some(node.color.inverse())
+                } else {
+                    self.simulate(&node.board, node.color)
+                }
+            };
+            tree.backpropagate(node, winner);
+        }
+    }
+
+    fn think(&self, color: Color, board: &Board, deadline: Option<Instant>) -> Action {
+        let previous = self.tree.borrow_mut().take();
+        let mut tree = previous
+            .and_then(|t| t.reroot(board, color))
+            .unwrap_or_else(|| Tree::new(board, color));
+
+        self.search(&mut tree, deadline);
+        let m = tree.best_move();
+
+        *self.tree.borrow_mut() = Some(tree);
+        m.into()
+    }
+}
+
+impl PlayerController for MctsAI {
+    fn name(&self) -> String {
+        format!("Mcts{}", self.rollout.name())
+    }
+
+    fn play(&self, color: Color, board: &Board) -> Action {
+        self.think(color, board, None)
+    }
+
+    fn play_with_clock(&self, color: Color, board: &Board, clock: &ClockInfo) -> Action {
+        self.think(color, board, Some(Instant::now() + clock.budget()))
+    }
+
+    fn reseed(&self, seed: u64) {
+        self.rollout.reseed(seed);
+    }
+
+    // A tree from an earlier game could be rerooted into this one and make it depend on game order
+    fn new_game(&self, color: Color, board: &Board) {
+        *self.tree.borrow_mut() = None;
+        self.rollout.new_game(color, board);
+    }
+
+    // The rollout plays both sides of the simulations, so it is only told when games start and end
+    fn game_over(&self, result: PlayerResult, termination: Termination) {
+        self.rollout.game_over(result, termination);
+    }
+}
+
+
+
+#[test]
+fn mcts_captures_king() {
+    let mut board = Board::empty();
+    board.set_piece(Pos::new(4, 0), Piece::King.colored(Color::White));
+    board.set_piece(Pos::new(3, 3), Piece::Queen.colored(Color::White));
+    board.set_piece(Pos::new(3, 7), Piece::King.colored(Color::Black));
+
+    let ai = MctsAI::new(500);
+    assert_eq!(ai.play(Color::White, &board), Action::Move(Move(Pos::new(3, 3), Pos::new(3, 7))));
+}
+
+#[test]
+fn mcts_reuses_tree() {
+    let board = Board::new();
+    let ai = MctsAI::new(200);
+    assert!(ai.play(Color::White, &board).as_move().is_some());
+
+    let tree = ai.tree.borrow_mut().take().unwrap();
+    let child = tree.root().children[0];
+    let grandchild = tree.nodes[child].children[0];
+    let next = tree.nodes[grandchild].board.clone();
+    let visits = tree.nodes[grandchild].visits;
+
+    let rerooted = tree.reroot(&next, Color::White).unwrap();
+    assert_eq!(rerooted.root().visits, visits);
+    assert!(rerooted.root().mv.is_none());
+    assert!(rerooted.nodes.iter().skip(1).all(|n| n.parent.is_some()));
+}
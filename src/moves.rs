@@ -17,7 +17,7 @@ pub struct PossibleMoveIterator<'a> {
 }
 
 impl<'a> PossibleMoveIterator<'a> {
-    pub fn new(board: &Board, color: Color) -> PossibleMoveIterator {
+    pub fn new(board: &Board, color: Color) -> PossibleMoveIterator<'_> {
         let allies = board.pieces(color);
         let enemies = board.pieces(color.inverse());
         let mut it = PossibleMoveIterator {
@@ -111,6 +111,38 @@ fn possible_moves_for_piece(board: &Board, colored: ColoredPiece, pos: Pos) -> B
 }
 
 
+// Squares attacked by the piece at pos, including squares occupied by pieces of its own color
+pub fn attacks(board: &Board, pos: Pos) -> BitBoard {
+    match board.piece_at(pos) {
+        Some(piece) => attacks_for_piece(board.occupied(), piece, pos),
+        _ => BitBoard::empty()
+    }
+}
+
+// Sliding pieces stop at the first occupied square, which lets callers reveal x-rays by removing pieces from occupied
+pub fn attacks_for_piece(occupied: BitBoard, colored: ColoredPiece, pos: Pos) -> BitBoard {
+    match colored.piece {
+        Piece::Empty => BitBoard::empty(),
+        Piece::Pawn => pawn_attacks(colored.color, pos),
+        _ => possible_moves_internal(BitBoard::empty().with(pos), occupied.without(pos), colored, pos)
+    }
+}
+
+pub fn pawn_attacks(color: Color, pos: Pos) -> BitBoard {
+    let mut attacks = BitBoard::empty();
+    let row = match color {
+        Color::White => pos.row() as isize + 1,
+        Color::Black => pos.row() as isize - 1,
+    };
+    for col in &[pos.col() as isize - 1, pos.col() as isize + 1] {
+        if is_pos_valid(*col, row) {
+            attacks.add(Pos::new(*col as usize, row as usize));
+        }
+    }
+    attacks
+}
+
+
 fn possible_moves_internal(allies: BitBoard, enemies: BitBoard, colored: ColoredPiece, pos: Pos) -> BitBoard {
     if colored.is_empty() {
         return BitBoard::empty();
@@ -0,0 +1,403 @@
+use crate::board::*;
+use crate::piece::*;
+use crate::pos::*;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+
+#[derive(Clone)]
+pub struct Opening {
+    pub name: String,
+    pub board: Board,
+    pub to_move: Color,
+}
+
+impl Opening {
+    pub fn new(name: &str, board: Board, to_move: Color) -> Opening {
+        Opening {
+            name: name.to_string(),
+            board,
+            to_move,
+        }
+    }
+
+    pub fn start() -> Opening {
+        Opening::new("start", Board::new(), Color::White)
+    }
+
+    pub fn fen(&self) -> String {
+        to_fen(&self.board, self.to_move)
+    }
+}
+
+
+// Start positions of a tournament, each one is played twice with colours reversed
+pub struct OpeningSuite {
+    openings: Vec<Opening>,
+}
+
+impl OpeningSuite {
+    pub fn new(openings: Vec<Opening>) -> OpeningSuite {
+        assert!(!openings.is_empty());
+        OpeningSuite {
+            openings,
+        }
+    }
+
+    // .pgn files are read as games, anything else as one EPD or FEN position per line
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<OpeningSuite> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        let openings = if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("pgn")) {
+            parse_pgn(&text)
+        } else {
+            parse_epd(&text)
+        };
+        match openings {
+            Ok(openings) if !openings.is_empty() => Ok(OpeningSuite::new(openings)),
+            Ok(_) => Err(io::Error::new(io::ErrorKind::InvalidData, "No opening found")),
+            Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.openings.len()
+    }
+
+    pub fn get(&self, index: usize) -> &Opening {
+        &self.openings[index % self.openings.len()]
+    }
+}
+
+
+// Where the start positions of a tournament come from
+pub enum OpeningSource {
+    Suite(OpeningSuite),
+    // Uniformly random moves from the initial position, derived from the game seed
+    RandomPlies(usize),
+}
+
+// Keeps the opening moves independent from the controllers' randomness, which is also derived from the game seed
+const RANDOM_OPENING_SEED_MIX: u64 = 0x5EED_0F0A_11CE_5EED;
+
+// Moves capturing a king are never picked, the line stops early if they are the only ones left
+pub fn random_opening(plies: usize, seed: u64) -> Opening {
+    let mut rng = StdRng::seed_from_u64(seed ^ RANDOM_OPENING_SEED_MIX);
+    let mut board = Board::new();
+    let mut to_move = Color::White;
+    for _ in 0..plies {
+        let moves = board
+            .possible_moves(to_move)
+            .filter(|m| board.piece_at(m.1).map(|p| p.piece) != Some(Piece::King))
+            .collect::<Vec<_>>();
+        if moves.is_empty() {
+            break;
+        }
+        board.do_move(moves[rng.gen_range(0, moves.len())]);
+        to_move = to_move.inverse();
+    }
+    Opening::new(&format!("random {} plies", plies), board, to_move)
+}
+
+
+fn piece_from_char(c: char) -> Option<Piece> {
+    match c.to_ascii_lowercase() {
+        'p' => Some(Piece::Pawn),
+        'r' => Some(Piece::Rook),
+        'n' => Some(Piece::Knight),
+        'b' => Some(Piece::Bishop),
+        'q' => Some(Piece::Queen),
+        'k' => Some(Piece::King),
+        _ => None,
+    }
+}
+
+// Only piece placement and side to move matter here, castling and en passant fields are ignored
+pub fn parse_fen(fen: &str) -> Result<(Board, Color), String> {
+    let mut fields = fen.split_whitespace();
+    let placement = fields.next().ok_or("Empty FEN")?;
+    let to_move = match fields.next() {
+        Some("w") | None => Color::White,
+        Some("b") => Color::Black,
+        Some(s) => return Err(format!("Invalid side to move '{}'", s)),
+    };
+
+    let ranks = placement.split('/').collect::<Vec<_>>();
+    if ranks.len() != 8 {
+        return Err(format!("Expected 8 ranks in '{}'", placement));
+    }
+
+    let mut board = Board::empty();
+    for (i, rank) in ranks.iter().enumerate() {
+        let row = 7 - i;
+        let mut col = 0;
+        for c in rank.chars() {
+            if let Some(empty) = c.to_digit(10) {
+                col += empty as usize;
+            } else {
+                let piece = piece_from_char(c).ok_or(format!("Invalid piece '{}'", c))?;
+                if col >= 8 {
+                    return Err(format!("Rank '{}' has more than 8 squares", rank));
+                }
+                let color = if c.is_ascii_uppercase() { Color::White } else { Color::Black };
+                board.set_piece(Pos::new(col, row), piece.colored(color));
+                col += 1;
+            }
+        }
+        if col != 8 {
+            return Err(format!("Rank '{}' doesn't have 8 squares", rank));
+        }
+    }
+
+    // Games end as soon as a king is missing, so a position without exactly one per side can't be played from
+    for color in [Color::White, Color::Black] {
+        let kings = placement.chars().filter(|&c| c == Piece::King.colored(color).char_for_piece()).count();
+        if kings != 1 {
+            return Err(format!("Expected one {} king in '{}', found {}", color, placement, kings));
+        }
+    }
+    Ok((board, to_move))
+}
+
+pub fn to_fen(board: &Board, to_move: Color) -> String {
+    let mut fen = String::new();
+    for row in (0..8).rev() {
+        let mut empty = 0;
+        for col in 0..8 {
+            match board.piece_at(Pos::new(col, row)) {
+                Some(p) => {
+                    if empty > 0 {
+                        fen.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    fen.push(p.char_for_piece());
+                }
+                None => empty += 1,
+            }
+        }
+        if empty > 0 {
+            fen.push_str(&empty.to_string());
+        }
+        if row > 0 {
+            fen.push('/');
+        }
+    }
+    fen.push_str(if to_move == Color::White { " w" } else { " b" });
+    fen.push_str(" - - 0 1");
+    fen
+}
+
+
+// One position per line, either a full FEN or EPD with operations such as id "name";
+pub fn parse_epd(text: &str) -> Result<Vec<Opening>, String> {
+    let mut openings = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (board, to_move) = parse_fen(line).map_err(|e| format!("line {}: {}", i + 1, e))?;
+        let name = epd_id(line).unwrap_or_else(|| format!("#{}", openings.len() + 1));
+        openings.push(Opening::new(&name, board, to_move));
+    }
+    Ok(openings)
+}
+
+fn epd_id(line: &str) -> Option<String> {
+    let start = line.find("id \"")? + 4;
+    let end = line[start..].find('"')? + start;
+    Some(line[start..end].to_string())
+}
+
+
+// Resolves a move in standard algebraic notation.
+// Castling, promotions and en passant don't exist in this game, so they are rejected like any other invalid move.
+pub fn parse_san(board: &Board, color: Color, san: &str) -> Result<Move, String> {
+    let invalid = || format!("Invalid move '{}'", san);
+    let s = san.trim_end_matches(['+', '#', '!', '?']);
+    if s.starts_with("O-O") || s.starts_with("0-0") || s.contains('=') {
+        return Err(format!("Unsupported move '{}'", san));
+    }
+
+    let mut chars = s.chars().filter(|&c| c != 'x' && c != '-').collect::<Vec<_>>();
+    let piece = match chars.first() {
+        Some(&c) if c.is_ascii_uppercase() => {
+            chars.remove(0);
+            piece_from_char(c).ok_or_else(invalid)?
+        }
+        _ => Piece::Pawn,
+    };
+    if chars.len() < 2 {
+        return Err(invalid());
+    }
+    let dst = Pos::from_str(&chars[chars.len() - 2..].iter().collect::<String>()).map_err(|_| invalid())?;
+
+    // What's left is the disambiguation: a file, a rank or both
+    let hint = &chars[..chars.len() - 2];
+    let file = hint.iter().find_map(|&c| "abcdefgh".find(c));
+    let rank = hint.iter().find_map(|&c| "12345678".find(c));
+
+    let candidates = board
+        .possible_moves(color)
+        .filter(|m| m.1 == dst)
+        .filter(|m| board.piece_at(m.0).map(|p| p.piece) == Some(piece))
+        .filter(|m| file.map_or(true, |f| m.0.col() == f) && rank.map_or(true, |r| m.0.row() == r))
+        .collect::<Vec<_>>();
+
+    // SAN only disambiguates between legal moves, so a pinned piece may not have been mentioned
+    let legal = candidates.iter().filter(|&&m| !leaves_king_attacked(board, color, m)).copied().collect::<Vec<_>>();
+    match (candidates.as_slice(), legal.as_slice()) {
+        ([m], _) | (_, [m]) => Ok(*m),
+        ([], _) => Err(invalid()),
+        _ => Err(format!("Ambiguous move '{}'", san)),
+    }
+}
+
+fn leaves_king_attacked(board: &Board, color: Color, m: Move) -> bool {
+    let after = board.moved(m);
+    match after.king_pos(color) {
+        Some(king) => !after.attackers(king, color.inverse(), after.occupied()).is_empty(),
+        None => true,
+    }
+}
+
+
+// Every game's moves are played from the start position, or from its FEN tag.
+// A line stops at the first move this game can't play, such as castling.
+pub fn parse_pgn(text: &str) -> Result<Vec<Opening>, String> {
+    let mut openings = Vec::new();
+    let mut tags: Vec<(String, String)> = Vec::new();
+    let mut movetext = String::new();
+
+    let mut flush = |tags: &mut Vec<(String, String)>, movetext: &mut String| -> Result<(), String> {
+        if tags.is_empty() && movetext.trim().is_empty() {
+            return Ok(());
+        }
+        let tag = |name: &str| tags.iter().find(|(n, _)| n == name).map(|(_, v)| v.clone());
+        let (mut board, mut to_move) = match tag("FEN") {
+            Some(fen) => parse_fen(&fen)?,
+            None => (Board::new(), Color::White),
+        };
+        let name = tag("Opening").or_else(|| tag("Event")).unwrap_or_else(|| format!("#{}", openings.len() + 1));
+
+        for token in pgn_tokens(movetext) {
+            match parse_san(&board, to_move, &token) {
+                Ok(m) => {
+                    board.do_move(m);
+                    to_move = to_move.inverse();
+                }
+                Err(_) => break,
+            }
+        }
+        openings.push(Opening::new(&name, board, to_move));
+        tags.clear();
+        movetext.clear();
+        Ok(())
+    };
+
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            // A tag after movetext starts the next game
+            if !movetext.trim().is_empty() {
+                flush(&mut tags, &mut movetext)?;
+            }
+            if let Some((name, value)) = parse_tag(line) {
+                tags.push((name, value));
+            }
+        } else {
+            movetext.push_str(line);
+            movetext.push('\n');
+        }
+    }
+    flush(&mut tags, &mut movetext)?;
+    Ok(openings)
+}
+
+fn parse_tag(line: &str) -> Option<(String, String)> {
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?;
+    let (name, value) = inner.split_once(' ')?;
+    Some((name.to_string(), value.trim().trim_matches('"').to_string()))
+}
+
+// Moves only: comments, variations, move numbers, annotations and results are dropped
+fn pgn_tokens(movetext: &str) -> Vec<String> {
+    let mut cleaned = String::new();
+    let (mut comment, mut variation) = (false, 0);
+    for c in movetext.chars() {
+        match c {
+            '{' => comment = true,
+            '}' => comment = false,
+            '(' if !comment => variation += 1,
+            ')' if !comment => variation -= 1,
+            c if !comment && variation == 0 => cleaned.push(c),
+            _ => {}
+        }
+    }
+
+    cleaned
+        .split_whitespace()
+        .map(|t| t.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.'))
+        .filter(|t| !t.is_empty() && !t.starts_with('$') && !["1-0", "0-1", "1/2-1/2", "*"].contains(t))
+        .map(|t| t.to_string())
+        .collect()
+}
+
+
+
+#[test]
+fn opening_fen_round_trip() {
+    let start = Opening::start();
+    assert_eq!(start.fen(), "rnbkqbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1");
+
+    let (board, to_move) = parse_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1").unwrap();
+    assert_eq!(to_move, Color::Black);
+    assert_eq!(board.piece_at(Pos::new(4, 3)), Some(Piece::Pawn.colored(Color::White)));
+    assert_eq!(to_fen(&board, to_move), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b - - 0 1");
+
+    assert!(parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w").is_err());
+}
+
+#[test]
+fn opening_fen_needs_one_king_per_side() {
+    assert!(parse_fen("4k3/8/8/8/8/8/8/4K3 w").is_ok());
+    assert!(parse_fen("8/8/8/8/8/8/8/4K3 w").is_err());
+    assert!(parse_fen("4k3/8/8/8/8/8/8/8 b").is_err());
+    assert!(parse_fen("8/8/8/8/8/8/8/8 w").is_err());
+    assert!(parse_fen("4k3/8/8/8/8/8/8/3KK3 w").is_err());
+    assert!(parse_fen("3kk3/8/8/8/8/8/8/4K3 w").is_err());
+}
+
+#[test]
+fn opening_pgn_lines() {
+    let pgn = r#"
+[Event "Italian"]
+[Result "*"]
+
+1. e4 e5 2. Nf3 {main line} Nc6 (2... d6 3. d4) 3. Bc4 Bc5 4. O-O Nf6 *
+
+[Event "Two knights"]
+[FEN "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3"]
+
+3. Bc4 Nf6 4. Ng5 d5 5. exd5 Nxd5 $6 *
+"#;
+    let openings = parse_pgn(pgn).unwrap();
+    assert_eq!(openings.len(), 2);
+
+    // Stops before castling
+    assert_eq!(openings[0].name, "Italian");
+    assert_eq!(openings[0].fen(), "r1bkq1nr/pppp1ppp/2n5/2b1p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w - - 0 1");
+
+    assert_eq!(openings[1].to_move, Color::White);
+    assert_eq!(openings[1].fen(), "r1bqkb1r/ppp2ppp/2n5/3np1N1/2B5/8/PPPP1PPP/RNBQK2R w - - 0 1");
+
+    let epd = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 id \"King's pawn\";\n";
+    let openings = parse_epd(epd).unwrap();
+    assert_eq!((openings[0].name.as_str(), openings[0].to_move), ("King's pawn", Color::Black));
+}
@@ -0,0 +1,300 @@
+use crate::bitboard::*;
+use crate::board::*;
+use crate::eval::*;
+use crate::piece::*;
+use crate::pos::*;
+
+const DEFAULT_TABLE_SIZE: usize = 4096;
+
+const DOUBLED: Score = Score::new(-10, -25);
+const ISOLATED: Score = Score::new(-8, -15);
+const BACKWARD: Score = Score::new(-7, -15);
+
+// Indexed by relative rank (0 is the pawn's own back rank)
+const PASSED_RANK: [Score; 8] = [
+    Score::new(0, 0), Score::new(5, 10), Score::new(10, 15), Score::new(15, 25),
+    Score::new(30, 45), Score::new(50, 75), Score::new(80, 120), Score::new(0, 0),
+];
+
+const CONNECTED_RANK: [Score; 8] = [
+    Score::new(0, 0), Score::new(5, 3), Score::new(7, 4), Score::new(10, 6),
+    Score::new(18, 10), Score::new(30, 18), Score::new(50, 30), Score::new(0, 0),
+];
+
+// Endgame bonus per square of distance between the kings and the square in front of a passed pawn
+const PASSED_ENEMY_KING_DISTANCE: i64 = 5;
+const PASSED_OWN_KING_DISTANCE: i64 = 2;
+
+
+#[derive(Debug, Clone, Copy)]
+pub struct PawnEntry {
+    pub key: u64,
+    // From white's point of view
+    pub score: Score,
+    pub passed: [BitBoard; 2],
+}
+
+impl PawnEntry {
+    fn empty() -> PawnEntry {
+        // key 0 is the key of a board without pawns, for which this entry is correct
+        PawnEntry {
+            key: 0,
+            score: Score::zero(),
+            passed: [BitBoard::empty(); 2],
+        }
+    }
+}
+
+
+pub struct PawnTable {
+    entries: Vec<PawnEntry>,
+    hits: u64,
+    misses: u64,
+}
+
+impl PawnTable {
+    pub fn new() -> PawnTable {
+        PawnTable::with_size(DEFAULT_TABLE_SIZE)
+    }
+
+    pub fn with_size(size: usize) -> PawnTable {
+        PawnTable {
+            entries: vec![PawnEntry::empty(); size.next_power_of_two()],
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn probe(&mut self, board: &Board) -> PawnEntry {
+        let key = board.pawn_hash();
+        let index = (key as usize) & (self.entries.len() - 1);
+        if self.entries[index].key == key {
+            self.hits += 1;
+            self.entries[index]
+        } else {
+            self.misses += 1;
+            let entry = evaluate_pawns(board);
+            self.entries[index] = entry;
+            entry
+        }
+    }
+
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+
+
+
+fn file_mask(col: usize) -> u64 {
+    0x0101_0101_0101_0101u64 << col
+}
+
+fn adjacent_files_mask(col: usize) -> u64 {
+    let mut mask = 0;
+    if col > 0 {
+        mask |= file_mask(col - 1);
+    }
+    if col < 7 {
+        mask |= file_mask(col + 1);
+    }
+    mask
+}
+
+fn row_mask(row: usize) -> u64 {
+    0xFFu64 << (row * 8)
+}
+
+// Rows strictly in front of row, from color's point of view
+fn rows_ahead_mask(color: Color, row: usize) -> u64 {
+    match color {
+        Color::White if row == 7 => 0,
+        Color::White => !0u64 << ((row + 1) * 8),
+        Color::Black => (1u64 << (row * 8)) - 1,
+    }
+}
+
+fn forward(color: Color, pos: Pos) -> Option<Pos> {
+    match color {
+        Color::White if pos.row() < 7 => Some(Pos::new(pos.col(), pos.row() + 1)),
+        Color::Black if pos.row() > 0 => Some(Pos::new(pos.col(), pos.row() - 1)),
+        _ => None
+    }
+}
+
+// Squares attacked by a pawn of color standing on pos
+fn pawn_attacks_mask(color: Color, pos: Pos) -> u64 {
+    match forward(color, pos) {
+        Some(front) => adjacent_files_mask(front.col()) & row_mask(front.row()),
+        None => 0
+    }
+}
+
+pub fn relative_rank(color: Color, pos: Pos) -> usize {
+    match color {
+        Color::White => pos.row(),
+        Color::Black => 7 - pos.row(),
+    }
+}
+
+pub fn is_passed(board: &Board, color: Color, pos: Pos) -> bool {
+    let theirs = board.pieces_of(Piece::Pawn, color.inverse()).raw();
+    let span = (file_mask(pos.col()) | adjacent_files_mask(pos.col())) & rows_ahead_mask(color, pos.row());
+    theirs & span == 0
+}
+
+// Structure terms only depend on pawns, so they can be cached by pawn hash
+pub fn evaluate_pawns(board: &Board) -> PawnEntry {
+    let mut entry = PawnEntry::empty();
+    entry.key = board.pawn_hash();
+
+    for &color in &[Color::White, Color::Black] {
+        let ours = board.pieces_of(Piece::Pawn, color).raw();
+        let theirs = board.pieces_of(Piece::Pawn, color.inverse()).raw();
+
+        let mut score = Score::zero();
+        for pos in BitBoard::from_raw(ours).iter() {
+            let col = pos.col();
+            let rank = relative_rank(color, pos);
+            let file = file_mask(col);
+            let adjacent = adjacent_files_mask(col);
+            let ahead = rows_ahead_mask(color, pos.row());
+
+            let doubled = ours & file & ahead != 0;
+            let isolated = ours & adjacent == 0;
+            let passed = !doubled && theirs & (file | adjacent) & ahead == 0;
+
+            let supported = ours & pawn_attacks_mask(color.inverse(), pos) != 0;
+            let phalanx = ours & adjacent & row_mask(pos.row()) != 0;
+
+            let backward = !isolated && !passed && ours & adjacent & !ahead == 0 && match forward(color, pos) {
+                Some(stop) => theirs & pawn_attacks_mask(color, stop) != 0,
+                None => false
+            };
+
+            if doubled {
+                score += DOUBLED;
+            }
+            if isolated {
+                score += ISOLATED;
+            }
+            if backward {
+                score += BACKWARD;
+            }
+            if supported || phalanx {
+                score += CONNECTED_RANK[rank];
+            }
+            if passed {
+                score += PASSED_RANK[rank];
+                entry.passed[color.index()].add(pos);
+            }
+        }
+
+        match color {
+            Color::White => entry.score += score,
+            Color::Black => entry.score += -score,
+        }
+    }
+
+    entry
+}
+
+// Kings matter a lot for passed pawns once the board empties, this depends on more than pawns so it is not cached
+pub fn passed_pawns_king_proximity(board: &Board, entry: &PawnEntry) -> Score {
+    let mut score = Score::zero();
+    for &color in &[Color::White, Color::Black] {
+        let (own_king, enemy_king) = match (board.king_pos(color), board.king_pos(color.inverse())) {
+            (Some(a), Some(b)) => (a, b),
+            _ => continue
+        };
+
+        let mut bonus = 0;
+        for pos in entry.passed[color.index()].iter() {
+            let weight = relative_rank(color, pos) as i64 - 2;
+            if weight <= 0 {
+                continue;
+            }
+            if let Some(stop) = forward(color, pos) {
+                let enemy = stop.distance(enemy_king) as i64 * PASSED_ENEMY_KING_DISTANCE;
+                let own = stop.distance(own_king) as i64 * PASSED_OWN_KING_DISTANCE;
+                bonus += (enemy - own) * weight;
+            }
+        }
+
+        match color {
+            Color::White => score += Score::new(0, bonus),
+            Color::Black => score += Score::new(0, -bonus),
+        }
+    }
+    score
+}
+
+
+
+
+#[test]
+fn pawn_structure_terms() {
+    let mut board = Board::empty();
+    board.set_piece(Pos::new(4, 0), Piece::King.colored(Color::White));
+    board.set_piece(Pos::new(4, 7), Piece::King.colored(Color::Black));
+
+    // doubled and isolated a-pawns for white, passed d-pawn
+    board.set_piece(Pos::new(0, 1), Piece::Pawn.colored(Color::White));
+    board.set_piece(Pos::new(0, 2), Piece::Pawn.colored(Color::White));
+    board.set_piece(Pos::new(3, 4), Piece::Pawn.colored(Color::White));
+    board.set_piece(Pos::new(6, 6), Piece::Pawn.colored(Color::Black));
+
+    let entry = evaluate_pawns(&board);
+    assert!(entry.passed[Color::White.index()].piece_at(Pos::new(3, 4)));
+    assert!(entry.passed[Color::White.index()].piece_at(Pos::new(0, 2)));
+    assert!(!entry.passed[Color::White.index()].piece_at(Pos::new(0, 1)));
+    assert!(entry.passed[Color::Black.index()].piece_at(Pos::new(6, 6)));
+
+    assert!(is_passed(&board, Color::White, Pos::new(3, 4)));
+    assert!(is_passed(&board, Color::Black, Pos::new(6, 6)));
+
+    // Score of kings and the given pawns, from white's side
+    let score = |white: &[&str], black: &[&str]| {
+        let mut board = Board::empty();
+        board.set_piece(Pos::new(4, 0), Piece::King.colored(Color::White));
+        board.set_piece(Pos::new(4, 7), Piece::King.colored(Color::Black));
+        for (pawns, color) in [(white, Color::White), (black, Color::Black)] {
+            for pos in pawns {
+                board.set_piece(pos.parse().unwrap(), Piece::Pawn.colored(color));
+            }
+        }
+        evaluate_pawns(&board).score
+    };
+
+    // a2 is doubled and not passed, both are isolated, a3 is passed
+    assert_eq!(score(&["a2", "a3"], &[]), DOUBLED + ISOLATED * 2 + PASSED_RANK[2]);
+    // Side by side, both connected and passed
+    assert_eq!(score(&["d4", "e4"], &[]), (CONNECTED_RANK[3] + PASSED_RANK[3]) * 2);
+    // e4 is supported by d3, which itself isn't connected
+    assert_eq!(score(&["d3", "e4"], &[]), CONNECTED_RANK[3] + PASSED_RANK[3] + PASSED_RANK[2]);
+    // d2 can't advance to d3 without being taken by c4 and has no pawn beside or behind to support it,
+    // e4 is passed and black's c4 is isolated
+    assert_eq!(score(&["d2", "e4"], &["c4"]), BACKWARD + PASSED_RANK[3] - ISOLATED);
+}
+
+#[test]
+fn pawn_table_uses_pawn_hash() {
+    let mut table = PawnTable::with_size(16);
+    let board = Board::new();
+    let a = table.probe(&board);
+
+    // moving a knight does not change the pawn hash
+    let moved = board.moved(Move(Pos::new(6, 0), Pos::new(5, 2)));
+    assert_eq!(board.pawn_hash(), moved.pawn_hash());
+    assert_ne!(board.hash(), moved.hash());
+
+    let b = table.probe(&moved);
+    assert_eq!(a.score, b.score);
+    assert_eq!(table.hit_rate(), 0.5);
+}
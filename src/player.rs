@@ -1,13 +1,126 @@
 use crate::board::*;
 use crate::piece::*;
 use crate::pos::*;
+use crate::clock::*;
+use crate::elo::*;
 
 use std::io;
 use std::str::FromStr;
 
+// What a controller does on its turn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Move(Move),
+    // Plays the move and offers a draw, the opponent answers in respond_to_draw
+    OfferDraw(Move),
+    // Only valid as answers to a draw offer
+    AcceptDraw,
+    DeclineDraw,
+    // Valid once the position has occurred three times with the same player to move
+    ClaimDraw,
+    Resign,
+}
+
+impl Action {
+    pub fn as_move(&self) -> Option<Move> {
+        match *self {
+            Action::Move(m) | Action::OfferDraw(m) => Some(m),
+            _ => None,
+        }
+    }
+}
+
+// Having no move to play is resigning
+impl From<Option<Move>> for Action {
+    fn from(m: Option<Move>) -> Action {
+        m.map(Action::Move).unwrap_or(Action::Resign)
+    }
+}
+
+
+// A game's result from one controller's side
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayerResult {
+    Win,
+    Loss,
+    Draw,
+}
+
+// Controllers are only ever given out by shared reference, state kept across moves or games goes behind a RefCell.
+// The game runner calls new_game, then play or play_with_clock on each turn, then game_over once for both players.
 pub trait PlayerController {
     fn name(&self) -> String;
-    fn play(&self, color: Color, board: &Board) -> Option<Move>;
+    fn play(&self, color: Color, board: &Board) -> Action;
+
+    // Called instead of play when the game has a time control, controllers that can manage their time should use it
+    fn play_with_clock(&self, color: Color, board: &Board, _clock: &ClockInfo) -> Action {
+        self.play(color, board)
+    }
+
+    // The opponent just offered a draw with its last move, anything but AcceptDraw declines it
+    fn respond_to_draw(&self, _color: Color, _board: &Board) -> Action {
+        Action::DeclineDraw
+    }
+
+    // Should change whenever the playing strength might, ratings are stored per name and version
+    fn version(&self) -> String {
+        "1".to_string()
+    }
+
+    // Called by the game runner before each game, controllers using randomness should reseed from it
+    fn reseed(&self, _seed: u64) {
+    }
+
+    // The starting position of a game, which isn't always the initial one
+    fn new_game(&self, _color: Color, _board: &Board) {
+    }
+
+    // board is the position after the move
+    fn opponent_moved(&self, _m: Move, _board: &Board) {
+    }
+
+    fn game_over(&self, _result: PlayerResult, _termination: Termination) {
+    }
+}
+
+
+// Lets boxed controllers, such as the ones built from specs, be used wherever a controller is expected
+impl PlayerController for Box<dyn PlayerController> {
+    fn name(&self) -> String {
+        (**self).name()
+    }
+
+    fn play(&self, color: Color, board: &Board) -> Action {
+        (**self).play(color, board)
+    }
+
+    fn play_with_clock(&self, color: Color, board: &Board, clock: &ClockInfo) -> Action {
+        (**self).play_with_clock(color, board, clock)
+    }
+
+    fn respond_to_draw(&self, color: Color, board: &Board) -> Action {
+        (**self).respond_to_draw(color, board)
+    }
+
+    fn version(&self) -> String {
+        (**self).version()
+    }
+
+    fn reseed(&self, seed: u64) {
+        (**self).reseed(seed)
+    }
+
+    fn new_game(&self, color: Color, board: &Board) {
+        (**self).new_game(color, board)
+    }
+
+    fn opponent_moved(&self, m: Move, board: &Board) {
+        (**self).opponent_moved(m, board)
+    }
+
+    fn game_over(&self, result: PlayerResult, termination: Termination) {
+        (**self).game_over(result, termination)
+    }
 }
 
 
@@ -27,18 +140,28 @@ impl PlayerController for Player {
         "Human player".to_string()
     }
 
-    fn play(&self, color: Color, board: &Board) -> Option<Move> {
+    fn play(&self, color: Color, board: &Board) -> Action {
         println!("{}", board);
-        println!("{}'s turn:", color);
+        println!("{}'s turn (a move such as e2e4, followed by \"draw\" to offer a draw, \"claim\" or \"resign\"):", color);
         loop {
             
             let mut input = String::new();
             match io::stdin().read_line(&mut input) {
-                Ok(_) => {
-                    if let Some(m) = Move::from_str(&input).ok() {
+                // End of input
+                Ok(0) => return Action::Resign,
+                Ok(_) => {}
+                Err(_) => continue,
+            }
+
+            let words = input.split_whitespace().collect::<Vec<_>>();
+            match words[..] {
+                ["resign"] => return Action::Resign,
+                ["claim"] => return Action::ClaimDraw,
+                [m] | [m, "draw"] => {
+                    if let Ok(m) = Move::from_str(m) {
                         if let Some(p) = board.piece_at(m.0) {
                             if p.color == color {
-                                return Some(m);
+                                return if words.len() == 2 { Action::OfferDraw(m) } else { Action::Move(m) };
                             }
                         }  
                         println!("Move is invalid.");
@@ -46,9 +169,152 @@ impl PlayerController for Player {
                         println!("Move could not be parsed.");
                     }
                 }
-                
-                Err(_) => ()
+                _ => println!("Move could not be parsed."),
             }
         }
     }
-}
\ No newline at end of file
+
+    fn respond_to_draw(&self, _color: Color, _board: &Board) -> Action {
+        println!("Your opponent offers a draw, accept? (y/n)");
+        let mut input = String::new();
+        match io::stdin().read_line(&mut input) {
+            Ok(_) if input.trim().eq_ignore_ascii_case("y") => Action::AcceptDraw,
+            _ => Action::DeclineDraw,
+        }
+    }
+
+    fn opponent_moved(&self, m: Move, _board: &Board) {
+        println!("Opponent played {}", m);
+    }
+
+    fn game_over(&self, result: PlayerResult, termination: Termination) {
+        let result = match result {
+            PlayerResult::Win => "You won",
+            PlayerResult::Loss => "You lost",
+            PlayerResult::Draw => "Draw",
+        };
+        println!("{} ({}).", result, termination.name());
+    }
+}
+
+
+
+#[test]
+fn player_lifecycle_hooks() {
+    use crate::ai::*;
+    use std::cell::RefCell;
+
+    // Keeps the game it was told about
+    struct Recorder {
+        ai: FirstMoveAI,
+        events: RefCell<Vec<String>>,
+    }
+
+    impl PlayerController for Recorder {
+        fn name(&self) -> String {
+            "Recorder".to_string()
+        }
+
+        fn play(&self, color: Color, board: &Board) -> Action {
+            self.events.borrow_mut().push("play".to_string());
+            self.ai.play(color, board)
+        }
+
+        fn new_game(&self, color: Color, _board: &Board) {
+            self.events.borrow_mut().push(format!("new {}", color));
+        }
+
+        fn opponent_moved(&self, _m: Move, _board: &Board) {
+            self.events.borrow_mut().push("moved".to_string());
+        }
+
+        fn game_over(&self, result: PlayerResult, _termination: Termination) {
+            self.events.borrow_mut().push(format!("{:?}", result));
+        }
+    }
+
+    let recorder = Recorder { ai: FirstMoveAI::new(), events: RefCell::new(Vec::new()) };
+    let settings = GameSettings { max_moves: 4, ..GameSettings::new() };
+    let outcome = play_once_with_white([&recorder, &FirstMoveAI::new()], &settings, 3, 1);
+    assert_eq!(outcome.winner, None);
+    assert_eq!(recorder.events.into_inner(), ["new Black", "moved", "play", "moved", "Draw"]);
+}
+
+#[test]
+fn player_draw_actions() {
+    use crate::ai::*;
+    use std::cell::Cell;
+
+    // Moves its king's knight out and back, claiming a draw on the given turn
+    struct Shuffler {
+        claim_at: usize,
+        turns: Cell<usize>,
+    }
+
+    impl PlayerController for Shuffler {
+        fn name(&self) -> String {
+            "Shuffler".to_string()
+        }
+
+        fn play(&self, color: Color, board: &Board) -> Action {
+            self.turns.set(self.turns.get() + 1);
+            if self.turns.get() == self.claim_at {
+                return Action::ClaimDraw;
+            }
+            let (home, out) = match color {
+                Color::White => ("g1", "f3"),
+                Color::Black => ("g8", "f6"),
+            };
+            let (home, out) = (Pos::from_str(home).unwrap(), Pos::from_str(out).unwrap());
+            if board.piece_at(home).is_some() { Action::Move(Move(home, out)) } else { Action::Move(Move(out, home)) }
+        }
+
+        fn respond_to_draw(&self, _color: Color, _board: &Board) -> Action {
+            Action::AcceptDraw
+        }
+    }
+
+    let shuffler = |claim_at| Shuffler { claim_at, turns: Cell::new(0) };
+    let settings = GameSettings::new();
+
+    // The starting position is back for the third time on white's fifth turn
+    let outcome = play_once_with_white([&shuffler(5), &shuffler(0)], &settings, 1, 0);
+    assert_eq!((outcome.winner, outcome.termination), (None, Termination::Repetition));
+    // A turn early the claim is declined and the shuffler moves instead
+    let outcome = play_once_with_white([&shuffler(4), &shuffler(0)], &settings, 1, 0);
+    assert_eq!((outcome.winner, outcome.termination), (None, Termination::MoveLimit));
+
+    struct Claimer;
+
+    impl PlayerController for Claimer {
+        fn name(&self) -> String {
+            "Claimer".to_string()
+        }
+
+        fn play(&self, _color: Color, _board: &Board) -> Action {
+            Action::ClaimDraw
+        }
+    }
+
+    // Only claiming would never end the turn
+    let outcome = play_once_with_white([&Claimer, &shuffler(0)], &settings, 1, 0);
+    assert_eq!((outcome.winner, outcome.termination), (Some(1), Termination::IllegalMove));
+
+    struct Offerer(FirstMoveAI);
+
+    impl PlayerController for Offerer {
+        fn name(&self) -> String {
+            "Offerer".to_string()
+        }
+
+        fn play(&self, color: Color, board: &Board) -> Action {
+            self.0.play(color, board).as_move().map(Action::OfferDraw).unwrap_or(Action::Resign)
+        }
+    }
+
+    // FirstMove declines, the shuffler accepts
+    let outcome = play_once_with_white([&Offerer(FirstMoveAI::new()), &FirstMoveAI::new()], &settings, 1, 0);
+    assert_ne!(outcome.termination, Termination::DrawAgreed);
+    let outcome = play_once_with_white([&Offerer(FirstMoveAI::new()), &shuffler(0)], &settings, 1, 0);
+    assert_eq!((outcome.winner, outcome.termination, outcome.moves), (None, Termination::DrawAgreed, 1));
+}
@@ -21,7 +21,7 @@ impl Pos {
         }
     }
 
-    pub fn from_index(index: usize) -> Pos {
+    pub const fn from_index(index: usize) -> Pos {
         debug_assert!(index < 64);
         Pos {
             i: index as IndexType
@@ -39,12 +39,19 @@ impl Pos {
     pub fn row(&self) -> usize {
         (self.i / 8) as usize
     }
+
+    // Number of king moves needed to go from self to other
+    pub fn distance(&self, other: Pos) -> usize {
+        let d_col = (self.col() as isize - other.col() as isize).abs();
+        let d_row = (self.row() as isize - other.row() as isize).abs();
+        d_col.max(d_row) as usize
+    }
 }
 
 
 impl fmt::Display for Pos {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}{}", ['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h'][self.row()], self.col() + 1)
+        write!(f, "{}{}", ['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h'][self.col()], self.row() + 1)
     }
 }
 
@@ -115,3 +122,22 @@ fn move_from_str() {
     
 }
 
+
+#[test]
+fn pos_to_str() {
+    assert_eq!(Pos::new(0, 0).to_string(), "a1");
+    assert_eq!(Pos::new(4, 1).to_string(), "e2");
+    assert_eq!(Move(Pos::new(6, 0), Pos::new(5, 2)).to_string(), "g1f3");
+
+}
+
+#[test]
+fn pos_display_is_file_then_rank() {
+    // The file is the column and the rank the row, off the diagonal swapping them gives another square
+    assert_eq!(Pos::new(7, 0).to_string(), "h1");
+    assert_eq!(Pos::new(0, 7).to_string(), "a8");
+    for i in 0..64 {
+        let pos = Pos::from_index(i);
+        assert_eq!(Pos::from_str(&pos.to_string()), Ok(pos));
+    }
+}
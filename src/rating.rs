@@ -0,0 +1,479 @@
+use crate::piece::*;
+use crate::tournament::*;
+
+use std::fmt::Write;
+
+// Bradley-Terry model with draws and white advantage, as in BayesElo (https://www.remi-coulom.fr/Bayesian-Elo/):
+//   P(white wins) = f(white - black + advantage - draw_elo)
+//   P(black wins) = f(black - white - advantage - draw_elo)
+//   P(draw) = 1 - P(white wins) - P(black wins)
+// with f(d) = 1 / (1 + 10^(-d / 400)).
+// All games are fitted at once, so ratings do not depend on the order games were played in.
+
+const K: f64 = std::f64::consts::LN_10 / 400.0;
+
+const DEFAULT_PRIOR: f64 = 2.0;
+const INITIAL_DRAW_ELO: f64 = 100.0;
+
+const MAX_ITERATIONS: usize = 200;
+const EPSILON: f64 = 1e-6;
+const HESSIAN_STEP: f64 = 1e-2;
+
+// z for a two-sided 95% confidence interval
+const CONFIDENCE_Z: f64 = 1.96;
+
+
+#[derive(Debug, Clone)]
+pub struct RatingFit {
+    pub ratings: Vec<f64>,
+    pub white_advantage: f64,
+    pub draw_elo: f64,
+    pub log_likelihood: f64,
+    pub iterations: usize,
+
+    // Covariance of the ratings, the inverse of the negated Hessian of the log likelihood at the optimum.
+    // The anchor's row and column are zero since its rating is fixed.
+    pub covariance: Vec<Vec<f64>>,
+}
+
+impl RatingFit {
+    pub fn standard_error(&self, player: usize) -> f64 {
+        self.covariance[player][player].max(0.0).sqrt()
+    }
+
+    // Half width of the 95% confidence interval
+    pub fn error_bar(&self, player: usize) -> f64 {
+        CONFIDENCE_Z * self.standard_error(player)
+    }
+
+    // Likelihood of superiority: probability that a is stronger than b
+    pub fn los(&self, a: usize, b: usize) -> f64 {
+        if a == b {
+            return 0.5;
+        }
+        let variance = self.covariance[a][a] + self.covariance[b][b] - 2.0 * self.covariance[a][b];
+        let diff = self.ratings[a] - self.ratings[b];
+        if variance <= 0.0 {
+            return if diff > 0.0 { 1.0 } else if diff < 0.0 { 0.0 } else { 0.5 };
+        }
+        normal_cdf(diff / variance.sqrt())
+    }
+
+    pub fn los_matrix(&self) -> Vec<Vec<f64>> {
+        let n = self.ratings.len();
+        (0..n).map(|a| (0..n).map(|b| self.los(a, b)).collect()).collect()
+    }
+
+    pub fn los_table(&self, names: &[String]) -> String {
+        let width = names.iter().map(|n| n.len()).max().unwrap_or(0).max(6);
+        let mut table = String::new();
+
+        let _ = write!(table, "{:width$}", "", width = width + 2);
+        for i in 0..names.len() {
+            let _ = write!(table, " {:>5}", i + 1);
+        }
+        for (a, name) in names.iter().enumerate() {
+            let _ = write!(table, "\n{:width$}", format!("{} {}", a + 1, name), width = width + 2);
+            for b in 0..names.len() {
+                if a == b {
+                    let _ = write!(table, " {:>5}", "-");
+                } else {
+                    let _ = write!(table, " {:>4}%", (self.los(a, b) * 100.0).round() as i64);
+                }
+            }
+        }
+        table
+    }
+}
+
+
+// Wins, draws and losses from white's point of view, for every (white, black) pair
+#[derive(Debug, Clone)]
+pub struct ResultMatrix {
+    size: usize,
+    counts: Vec<[f64; 3]>,
+}
+
+impl ResultMatrix {
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn new(size: usize) -> ResultMatrix {
+        ResultMatrix {
+            size,
+            counts: vec![[0.0; 3]; size * size],
+        }
+    }
+
+    pub fn from_results(size: usize, results: &[GameResult]) -> ResultMatrix {
+        let mut matrix = ResultMatrix::new(size);
+        for r in results {
+            matrix.add(r.white_player(), r.black_player(), r.winner_color());
+        }
+        matrix
+    }
+
+    pub fn add(&mut self, white: usize, black: usize, winner: Option<Color>) {
+        let slot = match winner {
+            Some(Color::White) => 0,
+            None => 1,
+            Some(Color::Black) => 2,
+        };
+        self.counts[white * self.size + black][slot] += 1.0;
+    }
+
+    // Virtual draws between every pair of players who met, to keep ratings finite for perfect scores
+    fn with_prior(&self, prior: f64) -> ResultMatrix {
+        let mut matrix = self.clone();
+        for w in 0..self.size {
+            for b in 0..self.size {
+                let games = |i: usize| self.counts[i].iter().sum::<f64>();
+                if w != b && games(w * self.size + b) + games(b * self.size + w) > 0.0 {
+                    matrix.counts[w * self.size + b][1] += prior / 4.0;
+                    matrix.counts[b * self.size + w][1] += prior / 4.0;
+                }
+            }
+        }
+        matrix
+    }
+
+    fn pairs(&self) -> impl Iterator<Item = (usize, usize, [f64; 3])> + '_ {
+        let size = self.size;
+        self.counts.iter().enumerate()
+            .filter(|(_, c)| c.iter().sum::<f64>() > 0.0)
+            .map(move |(i, c)| (i / size, i % size, *c))
+    }
+}
+
+
+fn f(d: f64) -> f64 {
+    1.0 / (1.0 + 10.0_f64.powf(-d / 400.0))
+}
+
+
+// Parameters being fitted: every rating but the anchor's, then white advantage and draw elo
+struct Model<'a> {
+    matrix: &'a ResultMatrix,
+    anchor: usize,
+    anchor_rating: f64,
+}
+
+impl<'a> Model<'a> {
+    fn parameter_count(&self) -> usize {
+        self.matrix.size + 1
+    }
+
+    fn ratings(&self, x: &[f64]) -> Vec<f64> {
+        let mut ratings = Vec::with_capacity(self.matrix.size);
+        ratings.extend_from_slice(&x[..self.anchor]);
+        ratings.push(self.anchor_rating);
+        ratings.extend_from_slice(&x[self.anchor..(self.matrix.size - 1)]);
+        ratings
+    }
+
+    fn rating_index(&self, player: usize) -> Option<usize> {
+        if player == self.anchor {
+            None
+        } else if player < self.anchor {
+            Some(player)
+        } else {
+            Some(player - 1)
+        }
+    }
+
+    fn log_likelihood(&self, x: &[f64]) -> f64 {
+        let ratings = self.ratings(x);
+        let (advantage, draw_elo) = (x[x.len() - 2], x[x.len() - 1]);
+        if draw_elo < 0.0 {
+            return f64::NEG_INFINITY;
+        }
+
+        let mut ll = 0.0;
+        for (w, b, [wins, draws, losses]) in self.matrix.pairs() {
+            let d = ratings[w] - ratings[b] + advantage;
+            let pw = f(d - draw_elo);
+            let pl = f(-d - draw_elo);
+            let pd = 1.0 - pw - pl;
+            ll += wins * pw.ln() + losses * pl.ln() + draws * pd.ln();
+        }
+        ll
+    }
+
+    fn gradient(&self, x: &[f64]) -> Vec<f64> {
+        let ratings = self.ratings(x);
+        let n = x.len();
+        let (advantage, draw_elo) = (x[n - 2], x[n - 1]);
+
+        let mut g = vec![0.0; n];
+        for (w, b, [wins, draws, losses]) in self.matrix.pairs() {
+            let d = ratings[w] - ratings[b] + advantage;
+            let pw = f(d - draw_elo);
+            let pl = f(-d - draw_elo);
+            let pd = 1.0 - pw - pl;
+
+            // f'(x) = K f(x) (1 - f(x))
+            let dpw = K * pw * (1.0 - pw);
+            let dpl = K * pl * (1.0 - pl);
+
+            let by_d = wins * K * (1.0 - pw) - losses * K * (1.0 - pl) + draws * (dpl - dpw) / pd;
+            let by_draw_elo = -wins * K * (1.0 - pw) - losses * K * (1.0 - pl) + draws * (dpw + dpl) / pd;
+
+            if let Some(i) = self.rating_index(w) {
+                g[i] += by_d;
+            }
+            if let Some(i) = self.rating_index(b) {
+                g[i] -= by_d;
+            }
+            g[n - 2] += by_d;
+            g[n - 1] += by_draw_elo;
+        }
+        g
+    }
+
+    // Numerical differentiation of the analytic gradient
+    fn hessian(&self, x: &[f64]) -> Vec<Vec<f64>> {
+        let n = x.len();
+        let mut h = vec![vec![0.0; n]; n];
+        for j in 0..n {
+            let mut plus = x.to_vec();
+            let mut minus = x.to_vec();
+            plus[j] += HESSIAN_STEP;
+            minus[j] -= HESSIAN_STEP;
+            let (gp, gm) = (self.gradient(&plus), self.gradient(&minus));
+            for i in 0..n {
+                h[i][j] = (gp[i] - gm[i]) / (2.0 * HESSIAN_STEP);
+            }
+        }
+        // Symmetrize to remove numerical noise
+        let transposed = (0..n).map(|i| (0..n).map(|j| h[j][i]).collect::<Vec<_>>()).collect::<Vec<_>>();
+        h.iter().zip(&transposed)
+            .map(|(row, col)| row.iter().zip(col).map(|(a, b)| (a + b) * 0.5).collect())
+            .collect()
+    }
+}
+
+
+pub struct MaxLikelihood {
+    pub anchor: usize,
+    pub anchor_rating: f64,
+    pub prior: f64,
+}
+
+impl MaxLikelihood {
+    pub fn new(anchor: usize, anchor_rating: f64) -> MaxLikelihood {
+        MaxLikelihood {
+            anchor,
+            anchor_rating,
+            prior: DEFAULT_PRIOR,
+        }
+    }
+
+    pub fn fit(&self, matrix: &ResultMatrix) -> RatingFit {
+        assert!(self.anchor < matrix.size);
+        let matrix = matrix.with_prior(self.prior);
+        let model = Model {
+            matrix: &matrix,
+            anchor: self.anchor,
+            anchor_rating: self.anchor_rating,
+        };
+
+        let n = model.parameter_count();
+        let mut x = vec![self.anchor_rating; n];
+        x[n - 2] = 0.0;
+        x[n - 1] = INITIAL_DRAW_ELO;
+        let mut ll = model.log_likelihood(&x);
+
+        let mut iterations = 0;
+        while iterations < MAX_ITERATIONS {
+            iterations += 1;
+
+            let g = model.gradient(&x);
+            let h = model.hessian(&x);
+            let neg_h = h.iter().map(|row| row.iter().map(|v| -v).collect()).collect();
+
+            // Newton step, or gradient ascent if the Hessian is not usable here
+            let step = match solve(neg_h, g.clone()) {
+                Some(step) if dot(&step, &g) > 0.0 => step,
+                _ => g.clone(),
+            };
+
+            let mut t = 1.0;
+            let mut improved = false;
+            for _ in 0..40 {
+                let candidate = x.iter().zip(&step).map(|(a, s)| a + t * s).collect::<Vec<_>>();
+                let candidate_ll = model.log_likelihood(&candidate);
+                if candidate_ll.is_finite() && candidate_ll >= ll {
+                    let moved = step.iter().map(|s| (s * t).abs()).fold(0.0, f64::max);
+                    x = candidate;
+                    ll = candidate_ll;
+                    improved = moved > EPSILON;
+                    break;
+                }
+                t *= 0.5;
+            }
+
+            if !improved {
+                break;
+            }
+        }
+
+        let size = matrix.size;
+        let neg_h = model.hessian(&x).iter().map(|row| row.iter().map(|v| -v).collect()).collect();
+        let covariance = match invert(neg_h) {
+            Some(inverse) => (0..size).map(|a| (0..size).map(|b| {
+                match (model.rating_index(a), model.rating_index(b)) {
+                    (Some(i), Some(j)) => inverse[i][j],
+                    _ => 0.0
+                }
+            }).collect()).collect(),
+            None => vec![vec![0.0; size]; size],
+        };
+
+        RatingFit {
+            ratings: model.ratings(&x),
+            white_advantage: x[n - 2],
+            draw_elo: x[n - 1],
+            log_likelihood: ll,
+            iterations,
+            covariance,
+        }
+    }
+}
+
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn invert(a: Vec<Vec<f64>>) -> Option<Vec<Vec<f64>>> {
+    let n = a.len();
+    let mut columns = Vec::with_capacity(n);
+    for i in 0..n {
+        let mut unit = vec![0.0; n];
+        unit[i] = 1.0;
+        columns.push(solve(a.clone(), unit)?);
+    }
+    Some((0..n).map(|i| (0..n).map(|j| columns[j][i]).collect()).collect())
+}
+
+// Abramowitz and Stegun 7.1.26, precise to about 1e-7
+fn normal_cdf(x: f64) -> f64 {
+    let z = x.abs() / std::f64::consts::SQRT_2;
+    let t = 1.0 / (1.0 + 0.327_591_1 * z);
+    let poly = t * (0.254_829_592 + t * (-0.284_496_736 + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    let erf = 1.0 - poly * (-z * z).exp();
+    if x >= 0.0 {
+        0.5 * (1.0 + erf)
+    } else {
+        0.5 * (1.0 - erf)
+    }
+}
+
+// Gaussian elimination with partial pivoting
+fn solve(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let n = b.len();
+    for col in 0..n {
+        let pivot = (col..n).max_by(|i, j| a[*i][col].abs().partial_cmp(&a[*j][col].abs()).unwrap())?;
+        if a[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+
+        let pivot_row = a[col].clone();
+        for row in (col + 1)..n {
+            let factor = a[row][col] / pivot_row[col];
+            for (v, p) in a[row].iter_mut().zip(&pivot_row).skip(col) {
+                *v -= factor * p;
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let sum = ((row + 1)..n).map(|k| a[row][k] * x[k]).sum::<f64>();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
+}
+
+
+
+#[test]
+fn ml_ratings_are_order_independent() {
+    let mut games = Vec::new();
+    for i in 0..120 {
+        // 1 beats 0 three times out of four, 2 beats 1 as often, some draws
+        let (white, black) = if i % 2 == 0 { (0, 1) } else { (1, 0) };
+        let winner = match i % 8 {
+            0..=2 => Some(1),
+            3 => None,
+            _ => Some(if i % 3 == 0 { 1 } else { 0 }),
+        };
+        games.push((white, black, winner));
+        games.push((white + 1, black + 1, winner.map(|w| w + 1)));
+    }
+
+    let matrix_of = |games: &[(usize, usize, Option<usize>)]| {
+        let mut m = ResultMatrix::new(3);
+        for &(w, b, winner) in games {
+            let color = winner.map(|p| if p == w { Color::White } else { Color::Black });
+            m.add(w, b, color);
+        }
+        m
+    };
+
+    let fit = MaxLikelihood::new(0, 1000.0).fit(&matrix_of(&games));
+    assert_eq!(fit.ratings[0], 1000.0);
+    assert!(fit.ratings[1] > fit.ratings[0]);
+    assert!(fit.ratings[2] > fit.ratings[1]);
+    assert!(fit.draw_elo > 0.0);
+
+    games.reverse();
+    let reversed = MaxLikelihood::new(0, 1000.0).fit(&matrix_of(&games));
+    for (a, b) in fit.ratings.iter().zip(&reversed.ratings) {
+        assert!((a - b).abs() < 1e-9);
+    }
+}
+
+#[test]
+fn ml_even_score_gives_equal_ratings() {
+    let mut m = ResultMatrix::new(2);
+    for _ in 0..10 {
+        m.add(0, 1, Some(Color::White));
+        m.add(1, 0, Some(Color::White));
+        m.add(0, 1, None);
+        m.add(1, 0, None);
+    }
+    let fit = MaxLikelihood::new(1, 0.0).fit(&m);
+    assert!(fit.ratings[0].abs() < 1e-3);
+    assert!(fit.white_advantage > 0.0);
+}
+
+#[test]
+fn ml_error_bars_shrink_with_games() {
+    let fit_games = |n: usize| {
+        let mut m = ResultMatrix::new(3);
+        for i in 0..n {
+            m.add(0, 1, if i % 3 == 0 { Some(Color::White) } else { Some(Color::Black) });
+            m.add(1, 0, if i % 3 == 0 { None } else { Some(Color::White) });
+            m.add(1, 2, if i % 2 == 0 { Some(Color::White) } else { None });
+            m.add(2, 1, Some(Color::Black));
+        }
+        MaxLikelihood::new(0, 0.0).fit(&m)
+    };
+
+    let small = fit_games(10);
+    let large = fit_games(1000);
+    assert_eq!(small.error_bar(0), 0.0);
+    assert!(small.error_bar(1) > large.error_bar(1));
+    assert!(large.error_bar(2) > 0.0);
+
+    // 1 is clearly better than 0, and los is symmetric
+    assert!(large.los(1, 0) > 0.99);
+    assert!((large.los(1, 2) + large.los(2, 1) - 1.0).abs() < 1e-9);
+    assert!((normal_cdf(0.0) - 0.5).abs() < 1e-9);
+    assert!((normal_cdf(1.96) - 0.975).abs() < 1e-4);
+}
@@ -0,0 +1,454 @@
+use crate::ai::*;
+use crate::board::*;
+use crate::elo::*;
+use crate::mcts::*;
+use crate::piece::*;
+use crate::player::*;
+use crate::search::*;
+use crate::uci::*;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+// Builds controllers from specs such as Capture(Swarm), Random(checkmate=false) or AlphaBeta(depth=4, threads=2).
+// Arguments are given in the order of the controller's parameters or by name, and any argument is itself a spec,
+// so composite controllers nest: Capture(Mcts(iterations=200, rollout=Swarm), safe=true).
+// Plain values such as numbers and paths are specs without arguments, quotes allow spaces and parentheses in them.
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControllerSpec {
+    pub name: String,
+    // Named arguments have a key
+    pub args: Vec<(Option<String>, ControllerSpec)>,
+}
+
+impl ControllerSpec {
+    pub fn new(name: &str) -> ControllerSpec {
+        ControllerSpec {
+            name: name.to_string(),
+            args: Vec::new(),
+        }
+    }
+
+    // Matches the arguments to the parameters, positional ones first
+    fn bind(&self, params: &'static [&'static str]) -> Result<SpecArgs<'_>, String> {
+        let mut values = vec![None; params.len()];
+        let mut position = 0;
+        for (key, value) in &self.args {
+            let index = match key {
+                Some(key) => params.iter().position(|p| p == key).ok_or_else(|| format!("unknown parameter {}", key))?,
+                None => {
+                    position += 1;
+                    if position > params.len() {
+                        return Err(format!("expected at most {} arguments", params.len()));
+                    }
+                    position - 1
+                }
+            };
+            if values[index].is_some() {
+                return Err(format!("{} given twice", params[index]));
+            }
+            values[index] = Some(value);
+        }
+        Ok(SpecArgs {
+            params,
+            values,
+        })
+    }
+}
+
+impl FromStr for ControllerSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_spec(s)
+    }
+}
+
+impl fmt::Display for ControllerSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.name.is_empty() || self.name.contains(|c: char| c.is_whitespace() || "(),=\"".contains(c)) {
+            write!(f, "\"{}\"", self.name)?;
+        } else {
+            write!(f, "{}", self.name)?;
+        }
+        if !self.args.is_empty() {
+            write!(f, "(")?;
+            for (i, (key, value)) in self.args.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                if let Some(key) = key {
+                    write!(f, "{}=", key)?;
+                }
+                write!(f, "{}", value)?;
+            }
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+
+// Arguments of a spec, by parameter
+pub struct SpecArgs<'a> {
+    params: &'static [&'static str],
+    values: Vec<Option<&'a ControllerSpec>>,
+}
+
+impl<'a> SpecArgs<'a> {
+    fn get(&self, param: &str) -> Option<&'a ControllerSpec> {
+        let index = self.params.iter().position(|p| *p == param).expect("Parameter was not declared");
+        self.values[index]
+    }
+
+    pub fn optional<T: FromStr>(&self, param: &str) -> Result<Option<T>, String> {
+        match self.get(param) {
+            None => Ok(None),
+            Some(spec) if spec.args.is_empty() => spec.name.parse().map(Some).map_err(|_| format!("invalid value {} for {}", spec.name, param)),
+            Some(spec) => Err(format!("expected a value for {}, not {}", param, spec)),
+        }
+    }
+
+    pub fn value<T: FromStr>(&self, param: &str, default: T) -> Result<T, String> {
+        Ok(self.optional(param)?.unwrap_or(default))
+    }
+
+    pub fn required<T: FromStr>(&self, param: &str) -> Result<T, String> {
+        self.optional(param)?.ok_or_else(|| format!("missing {}", param))
+    }
+
+    // Durations are given in seconds
+    pub fn seconds(&self, param: &str) -> Result<Option<Duration>, String> {
+        match self.optional::<f64>(param)? {
+            Some(t) if t.is_finite() && t >= 0.0 => Ok(Some(Duration::from_secs_f64(t))),
+            Some(t) => Err(format!("invalid value {} for {}", t, param)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn controller(&self, param: &str, default: &str, registry: &Registry) -> Result<Box<dyn PlayerController>, String> {
+        match self.get(param) {
+            Some(spec) => registry.build(spec),
+            None => registry.build(&ControllerSpec::new(default)),
+        }
+    }
+}
+
+
+pub type ControllerConstructor = fn(&SpecArgs, &Registry) -> Result<Box<dyn PlayerController>, String>;
+
+#[derive(Clone)]
+struct RegistryEntry {
+    name: &'static str,
+    params: &'static [&'static str],
+    constructor: ControllerConstructor,
+}
+
+// Controller names are matched without case
+#[derive(Clone)]
+pub struct Registry {
+    entries: HashMap<String, RegistryEntry>,
+    checking: bool,
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        Registry {
+            entries: HashMap::new(),
+            checking: false,
+        }
+    }
+
+    // Every controller of the crate
+    pub fn standard() -> Registry {
+        let mut registry = Registry::new();
+        registry.register("FirstMove", &[], |_, _| Ok(Box::new(FirstMoveAI::new())));
+        registry.register("Random", &["checkmate", "seed"], random);
+        registry.register("Swarm", &[], |_, _| Ok(Box::new(SwarmAI::new())));
+        registry.register("Capture", &["fallback", "safe"], capture);
+        registry.register("AlphaBeta", &["depth", "threads", "log"], alpha_beta);
+        registry.register("Mcts", &["iterations", "time", "rollout"], mcts);
+        registry.register("Uci", &["path", "depth", "nodes", "time", "log"], uci);
+        registry
+    }
+
+    pub fn register(&mut self, name: &'static str, params: &'static [&'static str], constructor: ControllerConstructor) {
+        self.entries.insert(name.to_lowercase(), RegistryEntry { name, params, constructor });
+    }
+
+    // Names with their parameters, e.g. Capture(fallback, safe)
+    pub fn signatures(&self) -> Vec<String> {
+        let mut signatures = self.entries.values()
+            .map(|e| if e.params.is_empty() { e.name.to_string() } else { format!("{}({})", e.name, e.params.join(", ")) })
+            .collect::<Vec<_>>();
+        signatures.sort();
+        signatures
+    }
+
+    // Set while checking a spec, constructors that start something, such as an engine process, should only read their arguments
+    pub fn checking(&self) -> bool {
+        self.checking
+    }
+
+    // Reads every argument of the spec and its nested specs without starting anything
+    pub fn check(&self, spec: &ControllerSpec) -> Result<(), String> {
+        let registry = Registry {
+            entries: self.entries.clone(),
+            checking: true,
+        };
+        registry.build(spec).map(|_| ())
+    }
+
+    pub fn build(&self, spec: &ControllerSpec) -> Result<Box<dyn PlayerController>, String> {
+        let entry = self.entries.get(&spec.name.to_lowercase()).ok_or_else(|| format!("unknown controller {}", spec.name))?;
+        let args = spec.bind(entry.params).map_err(|e| format!("{}: {}", entry.name, e))?;
+        (entry.constructor)(&args, self).map_err(|e| format!("{}: {}", entry.name, e))
+    }
+
+    // The spec is checked first, then a single controller is built for the player's name and version.
+    // The tournament builds its own for every game, a build that fails then (e.g. the engine was removed) forfeits the game.
+    pub fn player(&self, spec: &str) -> Result<EloPlayer, String> {
+        let spec = parse_spec(spec)?;
+        self.check(&spec)?;
+        let controller = self.build(&spec)?;
+        let registry = self.clone();
+        Ok(EloPlayer::new_with_controller(&controller, move || {
+            registry.build(&spec).unwrap_or_else(|error| Box::new(FailedController { spec: spec.to_string(), error }))
+        }))
+    }
+}
+
+
+// Plays the games of a controller that could not be built, by resigning them
+struct FailedController {
+    spec: String,
+    error: String,
+}
+
+impl PlayerController for FailedController {
+    fn name(&self) -> String {
+        self.spec.clone()
+    }
+
+    fn play(&self, color: Color, _board: &Board) -> Action {
+        println!("{} ({}): {}", self.spec, color, self.error);
+        Action::Resign
+    }
+}
+
+
+fn random(args: &SpecArgs, _: &Registry) -> Result<Box<dyn PlayerController>, String> {
+    let checkmate = args.value("checkmate", true)?;
+    Ok(match args.optional("seed")? {
+        Some(seed) => Box::new(RandomAI::new_with_seed(checkmate, seed)),
+        None => Box::new(RandomAI::new(checkmate)),
+    })
+}
+
+fn capture(args: &SpecArgs, registry: &Registry) -> Result<Box<dyn PlayerController>, String> {
+    let fallback = args.controller("fallback", "Swarm", registry)?;
+    Ok(if args.value("safe", false)? {
+        Box::new(CaptureAI::new_safe_with_fallback(fallback))
+    } else {
+        Box::new(CaptureAI::new_with_fallback(fallback))
+    })
+}
+
+fn alpha_beta(args: &SpecArgs, _: &Registry) -> Result<Box<dyn PlayerController>, String> {
+    let depth = args.value("depth", 4)?;
+    let mut ai = if args.value("log", false)? { AlphaBetaAI::new_with_log(depth) } else { AlphaBetaAI::new(depth) };
+    ai.set_threads(args.value("threads", 1)?);
+    Ok(Box::new(ai))
+}
+
+fn mcts(args: &SpecArgs, registry: &Registry) -> Result<Box<dyn PlayerController>, String> {
+    let budget = match (args.optional("iterations")?, args.seconds("time")?) {
+        (Some(_), Some(_)) => return Err("iterations and time can't both be given".to_string()),
+        (_, Some(time)) => Budget::Time(time),
+        (iterations, None) => Budget::Iterations(iterations.unwrap_or(1000)),
+    };
+    Ok(Box::new(MctsAI::new_with_rollout(budget, args.controller("rollout", "Random", registry)?)))
+}
+
+fn uci(args: &SpecArgs, registry: &Registry) -> Result<Box<dyn PlayerController>, String> {
+    let path = args.required::<String>("path")?;
+    let limit = match (args.optional("depth")?, args.optional("nodes")?, args.seconds("time")?) {
+        (Some(depth), None, None) => Some(UciLimit::Depth(depth)),
+        (None, Some(nodes), None) => Some(UciLimit::Nodes(nodes)),
+        (None, None, Some(time)) => Some(UciLimit::MoveTime(time)),
+        (None, None, None) => None,
+        _ => return Err("only one of depth, nodes and time can be given".to_string()),
+    };
+    let log = args.value("log", false)?;
+    if registry.checking() {
+        // Never played, it only stands in for the engine
+        return Ok(Box::new(FirstMoveAI::new()));
+    }
+    let mut engine = UciEngineController::new(&path).map_err(|e| format!("could not start {}: {}", path, e))?;
+    if let Some(limit) = limit {
+        engine.set_limit(limit);
+    }
+    engine.set_log(log);
+    Ok(Box::new(engine))
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Open,
+    Close,
+    Comma,
+    Equals,
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' | ',' | '=' => {
+                chars.next();
+                tokens.push(match c {
+                    '(' => Token::Open,
+                    ')' => Token::Close,
+                    ',' => Token::Comma,
+                    _ => Token::Equals,
+                });
+            }
+            '"' => {
+                chars.next();
+                let mut word = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated quote".to_string()),
+                    }
+                }
+                tokens.push(Token::Word(word));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "(),=\"".contains(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+pub fn parse_spec(s: &str) -> Result<ControllerSpec, String> {
+    let tokens = tokenize(s)?;
+    let mut position = 0;
+    let spec = parse_tokens(&tokens, &mut position)?;
+    match tokens.get(position) {
+        None => Ok(spec),
+        Some(token) => Err(format!("unexpected {:?} in {}", token, s)),
+    }
+}
+
+// spec = word [ "(" [ arg { "," arg } ] ")" ], arg = [ word "=" ] spec
+fn parse_tokens(tokens: &[Token], position: &mut usize) -> Result<ControllerSpec, String> {
+    let mut spec = match tokens.get(*position) {
+        Some(Token::Word(word)) => ControllerSpec::new(word),
+        Some(token) => return Err(format!("expected a name, found {:?}", token)),
+        None => return Err("expected a name".to_string()),
+    };
+    *position += 1;
+
+    if tokens.get(*position) != Some(&Token::Open) {
+        return Ok(spec);
+    }
+    *position += 1;
+    if tokens.get(*position) == Some(&Token::Close) {
+        *position += 1;
+        return Ok(spec);
+    }
+
+    loop {
+        let key = match (tokens.get(*position), tokens.get(*position + 1)) {
+            (Some(Token::Word(key)), Some(Token::Equals)) => {
+                *position += 2;
+                Some(key.clone())
+            }
+            _ => None,
+        };
+        spec.args.push((key, parse_tokens(tokens, position)?));
+
+        match tokens.get(*position) {
+            Some(Token::Comma) => *position += 1,
+            Some(Token::Close) => {
+                *position += 1;
+                return Ok(spec);
+            }
+            _ => return Err(format!("expected , or ) in the arguments of {}", spec.name)),
+        }
+    }
+}
+
+
+
+#[test]
+fn registry_builds_specs() {
+    let spec = parse_spec("Capture(Random(checkmate=false), safe=true)").unwrap();
+    assert_eq!(spec.to_string(), "Capture(Random(checkmate=false), safe=true)");
+    assert_eq!(parse_spec("Uci(\"my engine\")").unwrap().args[0].1.name, "my engine");
+
+    let registry = Registry::standard();
+    let name = |s: &str| registry.build(&parse_spec(s).unwrap()).map(|c| c.name());
+    assert_eq!(name("Capture(Swarm)"), Ok("SwarmCapture".to_string()));
+    assert_eq!(name("capture(fallback=Random, safe=true)"), Ok("RandomSafeCapture".to_string()));
+    assert_eq!(name("AlphaBeta(depth=2, threads=3)"), Ok("AlphaBeta2x3".to_string()));
+    assert_eq!(name("Mcts(time=0.01, rollout=Capture)"), Ok("MctsSwarmCapture".to_string()));
+
+    assert!(name("Capture(Swarm, true, 3)").is_err());
+    assert!(name("Capture(Swarm, fallback=Random)").is_err());
+    assert!(name("Random(check=true)").is_err());
+    assert!(name("AlphaBeta(depth=deep)").is_err());
+    assert!(name("Capture(Nothing)").is_err());
+    assert!(name("Random(checkmate=Swarm(1))").is_err());
+    assert!(parse_spec("Capture(Swarm").is_err());
+    assert!(parse_spec("Capture(Swarm))").is_err());
+    assert!(parse_spec("(Swarm)").is_err());
+    assert!(parse_spec("Uci(\"path)").is_err());
+
+    // Checking doesn't start engines, the path doesn't even need to exist
+    let check = |s: &str| registry.check(&parse_spec(s).unwrap());
+    assert_eq!(check("Capture(Uci(\"no such engine\", depth=3))"), Ok(()));
+    assert!(check("Uci(\"no such engine\", depth=3, nodes=100)").is_err());
+    assert!(check("Uci(depth=3)").is_err());
+    assert!(registry.player("Uci(\"no such engine\")").is_err());
+}
+
+#[test]
+fn registry_player_resigns_when_a_build_fails() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Builds the first two times, once for the check and once for the player's name
+    static BUILDS: AtomicUsize = AtomicUsize::new(0);
+    let mut registry = Registry::standard();
+    registry.register("Flaky", &[], |_, _| match BUILDS.fetch_add(1, Ordering::Relaxed) {
+        0 | 1 => Ok(Box::new(FirstMoveAI::new())),
+        _ => Err("gone".to_string()),
+    });
+
+    let player = registry.player("Flaky").unwrap();
+    assert_eq!(player.name(), FirstMoveAI::new().name());
+    let controller = player.controller();
+    assert_eq!(controller.name(), "Flaky");
+    assert_eq!(controller.play(Color::White, &Board::new()), Action::Resign);
+}
@@ -0,0 +1,331 @@
+use crate::tournament::*;
+
+use std::cmp;
+
+// Pairings are (white, black).
+// Schedulers hand out rounds of games, and get the results of the previous round back before the next one.
+pub trait Scheduler {
+    fn next_round(&mut self, last_round: &[GameResult]) -> Option<Vec<(usize, usize)>>;
+}
+
+
+// Every player meets every other player once per cycle, with colours reversed on odd cycles.
+// Static schedules are handed out as a single round so the tournament can spread all games over its threads.
+pub struct RoundRobin {
+    players: usize,
+    cycles: usize,
+    done: bool,
+}
+
+impl RoundRobin {
+    pub fn new(players: usize, cycles: usize) -> RoundRobin {
+        assert!(players > 1);
+        RoundRobin {
+            players,
+            cycles,
+            done: false,
+        }
+    }
+
+    pub fn pairings(&self) -> Vec<(usize, usize)> {
+        let cycle = round_robin_cycle(self.players);
+        (0..self.cycles)
+            .flat_map(|c| cycle.iter().map(move |&(a, b)| if c % 2 == 0 { (a, b) } else { (b, a) }))
+            .collect()
+    }
+}
+
+impl Scheduler for RoundRobin {
+    fn next_round(&mut self, _last_round: &[GameResult]) -> Option<Vec<(usize, usize)>> {
+        if self.done {
+            return None;
+        }
+        self.done = true;
+        Some(self.pairings())
+    }
+}
+
+// Circle method: the last player stays in place while the others rotate.
+// Only the fixed player's colour needs to alternate for everybody to stay within one game of even.
+fn round_robin_cycle(players: usize) -> Vec<(usize, usize)> {
+    // With an odd count, the extra slot is a bye
+    let slots = players + players % 2;
+    let mut pairings = Vec::new();
+    for round in 0..slots - 1 {
+        for i in 0..slots / 2 {
+            let (a, b) = if i == 0 {
+                if round % 2 == 0 { (round, slots - 1) } else { (slots - 1, round) }
+            } else {
+                ((round + i) % (slots - 1), (round + slots - 1 - i) % (slots - 1))
+            };
+            if a < players && b < players {
+                pairings.push((a, b));
+            }
+        }
+    }
+    pairings
+}
+
+
+// One player against every other player of the field
+pub struct Gauntlet {
+    players: usize,
+    challenger: usize,
+    cycles: usize,
+    done: bool,
+}
+
+impl Gauntlet {
+    pub fn new(players: usize, challenger: usize, cycles: usize) -> Gauntlet {
+        assert!(players > 1 && challenger < players);
+        Gauntlet {
+            players,
+            challenger,
+            cycles,
+            done: false,
+        }
+    }
+
+    // The challenger's colour alternates between opponents and between cycles
+    pub fn pairings(&self) -> Vec<(usize, usize)> {
+        let opponents = (0..self.players).filter(|&p| p != self.challenger).collect::<Vec<_>>();
+        let mut pairings = Vec::new();
+        for c in 0..self.cycles {
+            for (i, &opponent) in opponents.iter().enumerate() {
+                if (c + i) % 2 == 0 {
+                    pairings.push((self.challenger, opponent));
+                } else {
+                    pairings.push((opponent, self.challenger));
+                }
+            }
+        }
+        pairings
+    }
+}
+
+impl Scheduler for Gauntlet {
+    fn next_round(&mut self, _last_round: &[GameResult]) -> Option<Vec<(usize, usize)>> {
+        if self.done {
+            return None;
+        }
+        self.done = true;
+        Some(self.pairings())
+    }
+}
+
+
+// Swiss system: players with similar scores meet, without rematches when it can be avoided.
+// Scores are in half points, a bye is worth a win.
+pub struct Swiss {
+    rounds: usize,
+    played_rounds: usize,
+    scores: Vec<usize>,
+    opponents: Vec<Vec<usize>>,
+    // Whites minus blacks
+    colors: Vec<i64>,
+    byes: Vec<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwissStanding {
+    pub player: usize,
+    pub score: usize,
+    // Sum of the opponents' scores
+    pub buchholz: usize,
+}
+
+impl Swiss {
+    pub fn new(players: usize, rounds: usize) -> Swiss {
+        assert!(players > 1);
+        Swiss {
+            rounds,
+            played_rounds: 0,
+            scores: vec![0; players],
+            opponents: vec![Vec::new(); players],
+            colors: vec![0; players],
+            byes: vec![false; players],
+        }
+    }
+
+    pub fn buchholz(&self, player: usize) -> usize {
+        self.opponents[player].iter().map(|&o| self.scores[o]).sum()
+    }
+
+    // Best first, ties broken by Buchholz
+    pub fn standings(&self) -> Vec<SwissStanding> {
+        let mut standings = (0..self.scores.len())
+            .map(|player| SwissStanding {
+                player,
+                score: self.scores[player],
+                buchholz: self.buchholz(player),
+            })
+            .collect::<Vec<_>>();
+        standings.sort_by_key(|s| (cmp::Reverse(s.score), cmp::Reverse(s.buchholz), s.player));
+        standings
+    }
+
+    fn record(&mut self, result: &GameResult) {
+        let (white, black) = (result.white_player(), result.black_player());
+        self.opponents[white].push(black);
+        self.opponents[black].push(white);
+        self.colors[white] += 1;
+        self.colors[black] -= 1;
+
+        match result.winner {
+            Some(w) => {
+                let winner = if w == 0 { result.players.0 } else { result.players.1 };
+                self.scores[winner] += 2;
+            }
+            None => {
+                self.scores[white] += 1;
+                self.scores[black] += 1;
+            }
+        }
+    }
+
+    // The player who had more blacks gets white
+    fn colored(&self, a: usize, b: usize) -> (usize, usize) {
+        if self.colors[a] <= self.colors[b] { (a, b) } else { (b, a) }
+    }
+}
+
+impl Scheduler for Swiss {
+    fn next_round(&mut self, last_round: &[GameResult]) -> Option<Vec<(usize, usize)>> {
+        for r in last_round {
+            self.record(r);
+        }
+        if self.played_rounds >= self.rounds {
+            return None;
+        }
+        self.played_rounds += 1;
+
+        let mut order = self.standings().iter().map(|s| s.player).collect::<Vec<_>>();
+
+        // The lowest ranked player who hasn't had one yet sits out
+        if order.len() % 2 == 1 {
+            let bye = order.iter().rposition(|&p| !self.byes[p]).unwrap_or(order.len() - 1);
+            let player = order.remove(bye);
+            self.byes[player] = true;
+            self.scores[player] += 2;
+        }
+
+        // Rematches and colour imbalance are only allowed when there is no other way
+        let pairs = pair_swiss(&order, &self.opponents, Some(&self.colors))
+            .or_else(|| pair_swiss(&order, &self.opponents, None))
+            .unwrap_or_else(|| order.chunks(2).map(|c| (c[0], c[1])).collect());
+        Some(pairs.into_iter().map(|(a, b)| self.colored(a, b)).collect())
+    }
+}
+
+// Pairs players in ranking order, each with the best ranked opponent they haven't met, backtracking when stuck.
+// With colors, players due the same colour are kept apart.
+// Backtracking is exponential in the field size when few pairings are left, which is fine for the small fields played here.
+fn pair_swiss(order: &[usize], opponents: &[Vec<usize>], colors: Option<&[i64]>) -> Option<Vec<(usize, usize)>> {
+    let (&first, rest) = match order.split_first() {
+        Some(split) => split,
+        None => return Some(Vec::new()),
+    };
+
+    for (i, &opponent) in rest.iter().enumerate() {
+        if opponents[first].contains(&opponent) {
+            continue;
+        }
+        if colors.is_some_and(|c| c[first] * c[opponent] > 0) {
+            continue;
+        }
+        let remaining = rest.iter().enumerate().filter(|&(j, _)| j != i).map(|(_, &p)| p).collect::<Vec<_>>();
+        if let Some(mut pairs) = pair_swiss(&remaining, opponents, colors) {
+            pairs.insert(0, (first, opponent));
+            return Some(pairs);
+        }
+    }
+    None
+}
+
+
+
+#[test]
+fn round_robin_balances_games_and_colors() {
+    for players in 2..10 {
+        let pairings = RoundRobin::new(players, 1).pairings();
+        assert_eq!(pairings.len(), players * (players - 1) / 2);
+
+        for p in 0..players {
+            let whites = pairings.iter().filter(|&&(w, _)| w == p).count() as i64;
+            let blacks = pairings.iter().filter(|&&(_, b)| b == p).count() as i64;
+            assert_eq!(whites + blacks, players as i64 - 1);
+            assert!((whites - blacks).abs() <= 1);
+            for q in 0..players {
+                if p != q {
+                    assert_eq!(pairings.iter().filter(|&&(a, b)| (a, b) == (p, q) || (a, b) == (q, p)).count(), 1);
+                }
+            }
+        }
+
+        let twice = RoundRobin::new(players, 2).pairings();
+        for p in 0..players {
+            assert_eq!(twice.iter().filter(|&&(w, _)| w == p).count(), players - 1);
+        }
+    }
+}
+
+#[test]
+fn gauntlet_only_pairs_the_challenger() {
+    for players in 2..8 {
+        for challenger in [0, players - 1] {
+            let mut gauntlet = Gauntlet::new(players, challenger, 4);
+            let pairings = gauntlet.next_round(&[]).unwrap();
+            assert!(gauntlet.next_round(&[]).is_none());
+            assert_eq!(pairings.len(), (players - 1) * 4);
+            // The rest of the field only ever meets the challenger
+            assert!(pairings.iter().all(|&(w, b)| w != b && (w == challenger || b == challenger)));
+
+            // As often with each colour
+            for p in (0..players).filter(|&p| p != challenger) {
+                assert_eq!(pairings.iter().filter(|&&(w, b)| (w, b) == (p, challenger)).count(), 2);
+                assert_eq!(pairings.iter().filter(|&&(w, b)| (w, b) == (challenger, p)).count(), 2);
+            }
+        }
+    }
+}
+
+#[test]
+fn swiss_avoids_rematches() {
+    use crate::elo::Termination;
+    use std::time::Duration;
+
+    let players = 6;
+    let mut swiss = Swiss::new(players, 5);
+    let mut last = Vec::new();
+    let mut met = Vec::new();
+    let mut seed = 0;
+    while let Some(round) = swiss.next_round(&last) {
+        assert_eq!(round.len(), players / 2);
+        last = round
+            .iter()
+            .map(|&(white, black)| {
+                seed += 1;
+                assert!(!met.contains(&(white, black)) && !met.contains(&(black, white)));
+                met.push((white, black));
+                GameResult {
+                    players: (white, black),
+                    winner: if seed % 3 == 0 { None } else { Some((white + black) % 2) },
+                    white: 0,
+                    moves: 10,
+                    seed,
+                    termination: if seed % 3 == 0 { Termination::MoveLimit } else { Termination::KingCaptured },
+                    duration: Duration::ZERO,
+                    opening: None,
+                }
+            })
+            .collect();
+    }
+
+    for p in 0..players {
+        let whites = met.iter().filter(|&&(w, _)| w == p).count() as i64;
+        assert!((whites * 2 - 5).abs() <= 1);
+    }
+    let standings = swiss.standings();
+    assert_eq!(standings.iter().map(|s| s.score).sum::<usize>(), 2 * 15);
+    assert!(standings.windows(2).all(|w| (w[0].score, w[0].buchholz) >= (w[1].score, w[1].buchholz)));
+}
@@ -0,0 +1,508 @@
+use crate::board::*;
+use crate::piece::*;
+use crate::pos::*;
+use crate::player::*;
+use crate::eval::*;
+use crate::tt::*;
+use crate::clock::*;
+use crate::zobrist;
+
+use std::fmt;
+use std::cmp;
+use std::thread;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Instant, Duration};
+
+pub const MAX_PLY: usize = 64;
+
+const INFINITY: i64 = 1_000_000;
+pub const MATE_SCORE: i64 = 100_000;
+
+const ASPIRATION_WINDOW: i64 = 25;
+const ASPIRATION_MAX_DELTA: i64 = 1000;
+
+const NULL_MOVE: Move = Move(Pos::from_index(0), Pos::from_index(0));
+
+// Reading the time is too slow to do at every node
+const DEADLINE_CHECK_NODES: u64 = 1024;
+
+
+
+#[derive(Debug, Clone)]
+pub struct SearchInfo {
+    pub depth: usize,
+    pub score: i64,
+    pub nodes: u64,
+    pub time: Duration,
+    pub pv: Vec<Move>,
+}
+
+impl SearchInfo {
+    pub fn best_move(&self) -> Option<Move> {
+        self.pv.first().copied()
+    }
+
+    // Number of moves (not plies) until mate, negative if we are getting mated
+    pub fn mate_in(&self) -> Option<i64> {
+        if self.score.abs() < MATE_SCORE - MAX_PLY as i64 {
+            None
+        } else {
+            let plies = MATE_SCORE - self.score.abs();
+            Some(self.score.signum() * (plies + 1) / 2)
+        }
+    }
+}
+
+impl fmt::Display for SearchInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "info depth {} score ", self.depth)?;
+        match self.mate_in() {
+            Some(n) => write!(f, "mate {}", n)?,
+            None => write!(f, "cp {}", self.score)?,
+        }
+        write!(f, " nodes {} time {} pv", self.nodes, self.time.as_millis())?;
+        for m in &self.pv {
+            write!(f, " {}", m)?;
+        }
+        Ok(())
+    }
+}
+
+
+
+
+pub struct Search {
+    nodes: u64,
+    start: Instant,
+    evaluator: Evaluator,
+
+    tt: Arc<TranspositionTable>,
+    // Only set for helper threads, which are stopped once the main thread is done
+    stop: Option<Arc<AtomicBool>>,
+    // Once the first iteration is done, the search stops when it's reached
+    deadline: Option<Instant>,
+    timed_out: bool,
+
+    // Triangular PV table: pv[ply] holds the best line found from ply onward
+    pv: Vec<[Move; MAX_PLY]>,
+    pv_len: [usize; MAX_PLY],
+
+    // PV from the previous iteration, searched first
+    prev_pv: Vec<Move>,
+}
+
+impl Search {
+    pub fn new() -> Search {
+        Search::new_with_table(Arc::new(TranspositionTable::new()))
+    }
+
+    pub fn new_with_table(tt: Arc<TranspositionTable>) -> Search {
+        Search {
+            nodes: 0,
+            start: Instant::now(),
+            evaluator: Evaluator::new(),
+            tt,
+            stop: None,
+            deadline: None,
+            timed_out: false,
+            pv: vec![[NULL_MOVE; MAX_PLY]; MAX_PLY],
+            pv_len: [0; MAX_PLY],
+            prev_pv: Vec::new(),
+        }
+    }
+
+    fn new_helper(tt: Arc<TranspositionTable>, stop: Arc<AtomicBool>) -> Search {
+        let mut search = Search::new_with_table(tt);
+        search.stop = Some(stop);
+        search
+    }
+
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    fn stopped(&mut self) -> bool {
+        // Without a finished iteration there would be no move to return
+        if !self.timed_out && !self.prev_pv.is_empty() && self.nodes % DEADLINE_CHECK_NODES == 0 {
+            self.timed_out = self.deadline.map(|d| Instant::now() >= d).unwrap_or(false);
+        }
+        self.timed_out || self.stop.as_ref().map(|s| s.load(Ordering::Relaxed)).unwrap_or(false)
+    }
+
+    pub fn iterative_deepening<F: FnMut(&SearchInfo)>(&mut self, board: &Board, color: Color, max_depth: usize, mut on_iteration: F) -> Option<SearchInfo> {
+        self.nodes = 0;
+        self.start = Instant::now();
+        self.prev_pv.clear();
+        self.timed_out = false;
+
+        let max_depth = max_depth.clamp(1, MAX_PLY - 1);
+
+        let mut last: Option<SearchInfo> = None;
+        for depth in 1..=max_depth {
+            let score = match &last {
+                Some(info) if depth > 1 => self.aspiration(board, color, depth, info.score),
+                _ => self.root(board, color, depth, -INFINITY, INFINITY),
+            };
+
+            if self.stopped() {
+                break;
+            }
+
+            let info = SearchInfo {
+                depth,
+                score,
+                nodes: self.nodes,
+                time: self.start.elapsed(),
+                pv: self.pv[0][..self.pv_len[0]].to_vec(),
+            };
+
+            on_iteration(&info);
+
+            self.prev_pv = info.pv.clone();
+            let mated = info.mate_in().is_some();
+            last = Some(info);
+
+            if mated {
+                break;
+            }
+        }
+
+        last.filter(|info| !info.pv.is_empty())
+    }
+
+    fn aspiration(&mut self, board: &Board, color: Color, depth: usize, prev_score: i64) -> i64 {
+        let mut delta = ASPIRATION_WINDOW;
+        let mut alpha = prev_score - delta;
+        let mut beta = prev_score + delta;
+
+        loop {
+            let score = self.root(board, color, depth, alpha, beta);
+
+            if score <= alpha {
+                alpha = (score - delta).max(-INFINITY);
+            } else if score >= beta {
+                beta = (score + delta).min(INFINITY);
+            } else {
+                return score;
+            }
+
+            delta *= 2;
+            if delta > ASPIRATION_MAX_DELTA {
+                alpha = -INFINITY;
+                beta = INFINITY;
+            }
+        }
+    }
+
+    fn root(&mut self, board: &Board, color: Color, depth: usize, alpha: i64, beta: i64) -> i64 {
+        self.pvs(board, color, depth, 0, alpha, beta)
+    }
+
+    fn pvs(&mut self, board: &Board, color: Color, depth: usize, ply: usize, mut alpha: i64, beta: i64) -> i64 {
+        self.nodes += 1;
+        self.pv_len[ply] = 0;
+
+        if self.stopped() {
+            return 0;
+        }
+
+        if !board.has_king(color) {
+            return -MATE_SCORE + ply as i64;
+        }
+
+        if depth == 0 || ply + 1 >= MAX_PLY {
+            return self.quiescence(board, color, ply, alpha, beta);
+        }
+
+        let key = board.hash() ^ zobrist::side_key(color);
+        let pv_node = beta - alpha > 1;
+        let mut tt_move = None;
+        if let Some(entry) = self.tt.probe(key) {
+            tt_move = entry.mv;
+            // Cutting PV nodes would truncate the PV
+            if !pv_node && ply > 0 && entry.depth as usize >= depth {
+                let score = score_from_tt(entry.score, ply);
+                match entry.bound {
+                    Bound::Exact => return score,
+                    Bound::Lower if score >= beta => return score,
+                    Bound::Upper if score <= alpha => return score,
+                    _ => ()
+                }
+            }
+        }
+
+        let moves = self.ordered_moves(board, color, ply, tt_move, false);
+        if moves.is_empty() {
+            // Being unable to move loses the game
+            return -MATE_SCORE + ply as i64;
+        }
+
+        let original_alpha = alpha;
+        let mut best_move = None;
+        let mut first = true;
+        for m in moves {
+            let child = board.moved(m);
+            let enemy = color.inverse();
+
+            let score = if first {
+                -self.pvs(&child, enemy, depth - 1, ply + 1, -beta, -alpha)
+            } else {
+                let score = -self.pvs(&child, enemy, depth - 1, ply + 1, -alpha - 1, -alpha);
+                if score > alpha && score < beta {
+                    -self.pvs(&child, enemy, depth - 1, ply + 1, -beta, -alpha)
+                } else {
+                    score
+                }
+            };
+            first = false;
+
+            if score > alpha {
+                alpha = score;
+                best_move = Some(m);
+                self.update_pv(ply, m);
+                if alpha >= beta {
+                    break;
+                }
+            }
+        }
+
+        if !self.stopped() {
+            let bound = if alpha >= beta {
+                Bound::Lower
+            } else if alpha > original_alpha {
+                Bound::Exact
+            } else {
+                Bound::Upper
+            };
+            self.tt.store(key, TtEntry {
+                depth: depth as u8,
+                score: score_to_tt(alpha, ply),
+                bound,
+                mv: best_move.or(tt_move),
+            });
+        }
+
+        alpha
+    }
+
+    fn quiescence(&mut self, board: &Board, color: Color, ply: usize, mut alpha: i64, beta: i64) -> i64 {
+        self.nodes += 1;
+        self.pv_len[ply] = 0;
+
+        if !board.has_king(color) {
+            return -MATE_SCORE + ply as i64;
+        }
+
+        let stand_pat = self.evaluator.evaluate(board, color);
+        if stand_pat >= beta || ply + 1 >= MAX_PLY {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+
+        for m in self.ordered_moves(board, color, ply, None, true) {
+            let score = -self.quiescence(&board.moved(m), color.inverse(), ply + 1, -beta, -alpha);
+            if score > alpha {
+                alpha = score;
+                self.update_pv(ply, m);
+                if alpha >= beta {
+                    break;
+                }
+            }
+        }
+
+        alpha
+    }
+
+    fn update_pv(&mut self, ply: usize, m: Move) {
+        let child_len = self.pv_len[ply + 1];
+        self.pv[ply][ply] = m;
+        for i in (ply + 1)..child_len {
+            self.pv[ply][i] = self.pv[ply + 1][i];
+        }
+        self.pv_len[ply] = child_len.max(ply + 1);
+    }
+
+    fn ordered_moves(&self, board: &Board, color: Color, ply: usize, tt_move: Option<Move>, captures_only: bool) -> Vec<Move> {
+        let pv_move = self.prev_pv.get(ply).copied();
+        let mut moves = board.possible_moves(color)
+            .filter_map(|m| {
+                let victim = board.piece_at(m.1).map(|p| p.piece.score()).unwrap_or(0);
+                if captures_only && victim == 0 {
+                    return None;
+                }
+                let attacker = board.piece_at(m.0).map(|p| p.piece.score()).unwrap_or(0);
+                let order = if Some(m) == pv_move {
+                    i64::MAX
+                } else if Some(m) == tt_move {
+                    i64::MAX - 1
+                } else {
+                    victim * 1000 - attacker
+                };
+                Some((order, m))
+            })
+            .collect::<Vec<_>>();
+
+        moves.sort_by_key(|&(order, _)| cmp::Reverse(order));
+        moves.into_iter().map(|(_, m)| m).collect()
+    }
+}
+
+// Mate scores are stored relative to the node so they stay valid when reached from another ply
+fn score_to_tt(score: i64, ply: usize) -> i64 {
+    if score >= MATE_SCORE - MAX_PLY as i64 {
+        score + ply as i64
+    } else if score <= -MATE_SCORE + MAX_PLY as i64 {
+        score - ply as i64
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i64, ply: usize) -> i64 {
+    if score >= MATE_SCORE - MAX_PLY as i64 {
+        score - ply as i64
+    } else if score <= -MATE_SCORE + MAX_PLY as i64 {
+        score + ply as i64
+    } else {
+        score
+    }
+}
+
+
+// Lazy SMP: helper threads search the same position, sharing the transposition table with the main thread.
+// Odd helpers search one ply deeper so threads do not all follow the same path.
+// Only the main thread reports iterations and its result is the one returned, it's also the only one watching the deadline.
+pub fn lazy_smp<F: FnMut(&SearchInfo)>(board: &Board, color: Color, max_depth: usize, threads: usize, deadline: Option<Instant>, tt: Arc<TranspositionTable>, on_iteration: F) -> Option<SearchInfo> {
+    let stop = Arc::new(AtomicBool::new(false));
+    thread::scope(|s| {
+        for i in 1..threads {
+            let mut helper = Search::new_helper(tt.clone(), stop.clone());
+            s.spawn(move || {
+                helper.iterative_deepening(board, color, max_depth + i % 2, |_| {});
+            });
+        }
+
+        let mut search = Search::new_with_table(tt);
+        search.set_deadline(deadline);
+        let info = search.iterative_deepening(board, color, max_depth, on_iteration);
+        stop.store(true, Ordering::Relaxed);
+        info
+    })
+}
+
+
+
+
+pub struct AlphaBetaAI {
+    depth: usize,
+    log: bool,
+    threads: usize,
+    // Kept between moves
+    tt: Arc<TranspositionTable>,
+}
+
+impl AlphaBetaAI {
+    pub fn new(depth: usize) -> AlphaBetaAI {
+        AlphaBetaAI {
+            depth,
+            log: false,
+            threads: 1,
+            tt: Arc::new(TranspositionTable::new()),
+        }
+    }
+
+    pub fn new_with_log(depth: usize) -> AlphaBetaAI {
+        let mut ai = AlphaBetaAI::new(depth);
+        ai.log = true;
+        ai
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    fn search(&self, color: Color, board: &Board, deadline: Option<Instant>) -> Action {
+        let log = self.log;
+        let info = lazy_smp(board, color, self.depth, self.threads, deadline, self.tt.clone(), |info| {
+            if log {
+                println!("{}", info);
+            }
+        });
+
+        if log {
+            if let Some(info) = &info {
+                println!("{} ({}): bestmove {} score {} pv {}", self.name(), color, info.pv[0], info.score,
+                    info.pv.iter().map(|m| m.to_string()).collect::<Vec<_>>().join(" "));
+            }
+        }
+
+        info.and_then(|info| info.best_move()).into()
+    }
+}
+
+impl PlayerController for AlphaBetaAI {
+    fn name(&self) -> String {
+        if self.threads > 1 {
+            format!("AlphaBeta{}x{}", self.depth, self.threads)
+        } else {
+            format!("AlphaBeta{}", self.depth)
+        }
+    }
+
+    fn play(&self, color: Color, board: &Board) -> Action {
+        self.search(color, board, None)
+    }
+
+    // Still limited to its depth, the clock can only make it stop earlier
+    fn play_with_clock(&self, color: Color, board: &Board, clock: &ClockInfo) -> Action {
+        self.search(color, board, Some(Instant::now() + clock.budget()))
+    }
+
+    fn new_game(&self, _color: Color, _board: &Board) {
+        self.tt.clear();
+    }
+}
+
+
+
+#[test]
+fn search_takes_hanging_queen() {
+    let mut board = Board::empty();
+    board.set_piece(Pos::new(4, 0), Piece::King.colored(Color::White));
+    board.set_piece(Pos::new(0, 0), Piece::Rook.colored(Color::White));
+    board.set_piece(Pos::new(4, 7), Piece::King.colored(Color::Black));
+    board.set_piece(Pos::new(0, 5), Piece::Queen.colored(Color::Black));
+
+    let info = Search::new().iterative_deepening(&board, Color::White, 3, |_| {}).unwrap();
+    assert_eq!(info.best_move(), Some(Move(Pos::new(0, 0), Pos::new(0, 5))));
+    assert_eq!(info.depth, 3);
+    assert!(info.pv.len() >= 2);
+}
+
+#[test]
+fn search_finds_king_capture() {
+    let mut board = Board::empty();
+    board.set_piece(Pos::new(4, 0), Piece::King.colored(Color::White));
+    board.set_piece(Pos::new(3, 3), Piece::Queen.colored(Color::White));
+    board.set_piece(Pos::new(3, 7), Piece::King.colored(Color::Black));
+
+    let info = Search::new().iterative_deepening(&board, Color::White, 4, |_| {}).unwrap();
+    assert_eq!(info.best_move(), Some(Move(Pos::new(3, 3), Pos::new(3, 7))));
+    assert_eq!(info.mate_in(), Some(1));
+    assert!(info.to_string().starts_with("info depth 1 score mate 1"));
+}
+
+#[test]
+fn lazy_smp_agrees_with_single_thread() {
+    let mut board = Board::empty();
+    board.set_piece(Pos::new(4, 0), Piece::King.colored(Color::White));
+    board.set_piece(Pos::new(0, 0), Piece::Rook.colored(Color::White));
+    board.set_piece(Pos::new(4, 7), Piece::King.colored(Color::Black));
+    board.set_piece(Pos::new(0, 5), Piece::Queen.colored(Color::Black));
+
+    let tt = Arc::new(TranspositionTable::new());
+    let info = lazy_smp(&board, Color::White, 3, 4, None, tt, |_| {}).unwrap();
+    assert_eq!(info.best_move(), Some(Move(Pos::new(0, 0), Pos::new(0, 5))));
+    assert_eq!(info.depth, 3);
+}
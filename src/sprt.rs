@@ -0,0 +1,268 @@
+use crate::elo::*;
+use crate::tournament::*;
+
+use std::fmt;
+
+// Sequential probability ratio test between a candidate and a baseline.
+// Games are played in pairs on the same seed with colours reversed, and the log-likelihood ratio
+// of elo1 against elo0 is updated after each pair using the normal approximation of the generalized SPRT.
+
+pub const DEFAULT_ALPHA: f64 = 0.05;
+pub const DEFAULT_BETA: f64 = 0.05;
+const DEFAULT_MAX_PAIRS: usize = 20_000;
+// Added to the count of every outcome when all results are the same, so they still have a variance and the LLR keeps growing.
+// It pulls the mean toward the middle, which is why it isn't added otherwise.
+const PSEUDO_COUNT: f64 = 0.5;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SprtModel {
+    // Each game is a loss, draw or win
+    Trinomial,
+    // Each game pair scores 0, 0.5, 1, 1.5 or 2, which accounts for the correlation between the two games
+    Pentanomial,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hypothesis {
+    // The candidate is not better than elo0
+    H0,
+    // The candidate is at least elo1 better
+    H1,
+}
+
+
+// Results from the candidate's point of view
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SprtStats {
+    // Losses, draws and wins
+    pub games: [usize; 3],
+    // Pairs by total score in half points, from 0 to 4
+    pub pairs: [usize; 5],
+}
+
+impl SprtStats {
+    pub fn new() -> SprtStats {
+        SprtStats::default()
+    }
+
+    // Scores are in half points: 0 for a loss, 1 for a draw and 2 for a win
+    pub fn add_pair(&mut self, first: usize, second: usize) {
+        assert!(first <= 2 && second <= 2);
+        self.games[first] += 1;
+        self.games[second] += 1;
+        self.pairs[first + second] += 1;
+    }
+
+    pub fn pair_count(&self) -> usize {
+        self.pairs.iter().sum()
+    }
+
+    // Frequencies and scores of the outcomes of the given model
+    fn outcomes(&self, model: SprtModel) -> Vec<(f64, f64)> {
+        match model {
+            SprtModel::Trinomial => self.games.iter().enumerate().map(|(i, &n)| (n as f64, i as f64 / 2.0)).collect(),
+            SprtModel::Pentanomial => self.pairs.iter().enumerate().map(|(i, &n)| (n as f64, i as f64 / 4.0)).collect(),
+        }
+    }
+}
+
+
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+    pub model: SprtModel,
+    pub max_pairs: usize,
+    pub settings: GameSettings,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SprtReport {
+    pub stats: SprtStats,
+    pub llr: f64,
+    // None when max_pairs was reached before either hypothesis was accepted
+    pub result: Option<Hypothesis>,
+}
+
+impl Sprt {
+    pub fn new(elo0: f64, elo1: f64) -> Sprt {
+        assert!(elo0 < elo1);
+        Sprt {
+            elo0,
+            elo1,
+            alpha: DEFAULT_ALPHA,
+            beta: DEFAULT_BETA,
+            model: SprtModel::Pentanomial,
+            max_pairs: DEFAULT_MAX_PAIRS,
+            settings: GameSettings::new(),
+        }
+    }
+
+    // H0 is accepted when the LLR falls below this
+    pub fn lower_bound(&self) -> f64 {
+        (self.beta / (1.0 - self.alpha)).ln()
+    }
+
+    // H1 is accepted when the LLR rises above this
+    pub fn upper_bound(&self) -> f64 {
+        ((1.0 - self.beta) / self.alpha).ln()
+    }
+
+    pub fn llr(&self, stats: &SprtStats) -> f64 {
+        let outcomes = stats.outcomes(self.model);
+        if outcomes.iter().all(|&(count, _)| count == 0.0) {
+            return 0.0;
+        }
+
+        let (mut n, mut mean, mut variance) = moments(&outcomes);
+        if variance <= 0.0 {
+            let regularized = outcomes.iter().map(|&(count, score)| (count + PSEUDO_COUNT, score)).collect::<Vec<_>>();
+            (n, mean, variance) = moments(&regularized);
+        }
+
+        let (s0, s1) = (expected_score(self.elo0), expected_score(self.elo1));
+        (s1 - s0) * (2.0 * mean - s0 - s1) / (2.0 * variance / n)
+    }
+
+    pub fn decision(&self, llr: f64) -> Option<Hypothesis> {
+        if llr >= self.upper_bound() {
+            Some(Hypothesis::H1)
+        } else if llr <= self.lower_bound() {
+            Some(Hypothesis::H0)
+        } else {
+            None
+        }
+    }
+
+    // Plays game pairs until a hypothesis is accepted, on_pair is called with the report after each pair
+    pub fn run<F: FnMut(&SprtReport)>(&self, candidate: &EloPlayer, baseline: &EloPlayer, seed: u64, mut on_pair: F) -> SprtReport {
+        let mut report = SprtReport {
+            stats: SprtStats::new(),
+            llr: 0.0,
+            result: None,
+        };
+
+        for pair in 0..self.max_pairs {
+            let game_seed = game_seed(seed, pair);
+            let first = play_paired(candidate, baseline, &self.settings, game_seed, 0);
+            let second = play_paired(candidate, baseline, &self.settings, game_seed, 1);
+
+            report.stats.add_pair(first, second);
+            report.llr = self.llr(&report.stats);
+            report.result = self.decision(report.llr);
+            on_pair(&report);
+
+            if report.result.is_some() {
+                break;
+            }
+        }
+        report
+    }
+}
+
+impl fmt::Display for SprtReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [l, d, w] = self.stats.games;
+        let p = self.stats.pairs;
+        write!(f, "LLR: {:.2} games: (w: {}, l: {}, d: {}) pairs: [{}, {}, {}, {}, {}]", self.llr, w, l, d, p[0], p[1], p[2], p[3], p[4])?;
+        match self.result {
+            Some(Hypothesis::H0) => write!(f, " H0 accepted"),
+            Some(Hypothesis::H1) => write!(f, " H1 accepted"),
+            None => Ok(()),
+        }
+    }
+}
+
+// Expected score of a player rated elo above its opponent
+fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10.0_f64.powf(-elo / 400.0))
+}
+
+// Score of the candidate in half points, white is the index of the player who has white (0 for the candidate)
+fn play_paired(candidate: &EloPlayer, baseline: &EloPlayer, settings: &GameSettings, seed: u64, white: usize) -> usize {
+    let controllers = [candidate.controller(), baseline.controller()];
+    let outcome = play_once_with_white([&*controllers[0], &*controllers[1]], settings, seed, white);
+    match outcome.winner {
+        Some(0) => 2,
+        Some(_) => 0,
+        None => 1,
+    }
+}
+
+
+// Number of results, mean score and variance of the score
+fn moments(outcomes: &[(f64, f64)]) -> (f64, f64, f64) {
+    let n = outcomes.iter().map(|(count, _)| count).sum::<f64>();
+    let mean = outcomes.iter().map(|(count, score)| count * score).sum::<f64>() / n;
+    let variance = outcomes.iter().map(|(count, score)| count * (score - mean).powi(2)).sum::<f64>() / n;
+    (n, mean, variance)
+}
+
+
+
+#[test]
+fn sprt_llr_sign_follows_score() {
+    let sprt = Sprt::new(0.0, 10.0);
+    let mut even = SprtStats::new();
+    let mut better = SprtStats::new();
+    for _ in 0..100 {
+        even.add_pair(2, 1);
+        even.add_pair(0, 1);
+        better.add_pair(2, 1);
+        better.add_pair(1, 1);
+    }
+
+    assert!(sprt.llr(&even) < 0.0);
+    assert!(sprt.llr(&better) > sprt.upper_bound());
+    assert_eq!(sprt.decision(sprt.llr(&better)), Some(Hypothesis::H1));
+
+    let trinomial = Sprt { model: SprtModel::Trinomial, ..Sprt::new(0.0, 10.0) };
+    assert!(trinomial.llr(&better) > 0.0);
+}
+
+#[test]
+fn sprt_llr_is_unbiased_with_mixed_results() {
+    // 2 losses, 1 draw and 3 wins: n = 6, mean = 7/12, variance = 29/144
+    let mut stats = SprtStats::new();
+    stats.add_pair(0, 0);
+    stats.add_pair(1, 2);
+    stats.add_pair(2, 2);
+    let sprt = Sprt { model: SprtModel::Trinomial, ..Sprt::new(0.0, 50.0) };
+    let (s0, s1) = (0.5, expected_score(50.0));
+    let expected = (s1 - s0) * (2.0 * 7.0 / 12.0 - s0 - s1) / (2.0 * (29.0 / 144.0) / 6.0);
+    assert!((sprt.llr(&stats) - expected).abs() < 1e-9);
+
+    // Only wins have no variance, they are regularized and still favour the candidate
+    let mut wins = SprtStats::new();
+    wins.add_pair(2, 2);
+    assert!(sprt.llr(&wins) > 0.0);
+}
+
+#[test]
+fn sprt_accepts_stronger_candidate() {
+    use crate::ai::*;
+
+    let candidate = EloPlayer::new(|| CaptureAI::new_with_fallback(RandomAI::new(false)));
+    let baseline = EloPlayer::new(|| RandomAI::new(false));
+    let sprt = Sprt::new(0.0, 50.0);
+    let report = sprt.run(&candidate, &baseline, 7, |_| {});
+
+    assert_eq!(report.result, Some(Hypothesis::H1));
+    assert!(report.stats.pair_count() < 100);
+}
+
+#[test]
+fn sprt_stops_when_every_game_is_won() {
+    use crate::ai::*;
+
+    // Swarm beats FirstMove in every game, so every pair scores the same
+    let candidate = EloPlayer::new(SwarmAI::new);
+    let baseline = EloPlayer::new(FirstMoveAI::new);
+    let report = Sprt::new(0.0, 10.0).run(&candidate, &baseline, 3, |_| {});
+
+    assert_eq!(report.result, Some(Hypothesis::H1));
+    assert_eq!(report.stats.pairs[4], report.stats.pair_count());
+    assert!(report.stats.pair_count() < 50);
+}
@@ -0,0 +1,423 @@
+use crate::elo::*;
+use crate::glicko::*;
+use crate::schedule::*;
+use crate::opening::*;
+use crate::adjudication::*;
+use crate::clock::*;
+use crate::player::*;
+use crate::piece::*;
+
+use std::cmp;
+use std::thread;
+use std::time::{Duration, Instant};
+use std::sync::mpsc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use indicatif::ProgressBar;
+use rand::{Rng, SeedableRng, thread_rng};
+use rand::rngs::StdRng;
+
+
+#[derive(Debug, Clone, Copy)]
+pub struct GameResult {
+    pub players: (usize, usize),
+    // Index of the winner in players, None for a draw
+    pub winner: Option<usize>,
+    // Index of the player who had white in players
+    pub white: usize,
+    pub moves: usize,
+    pub seed: u64,
+    pub termination: Termination,
+    // Wall time of the game
+    pub duration: Duration,
+    // Index in the tournament's opening suite, None when starting from the initial position or a random opening
+    pub opening: Option<usize>,
+}
+
+impl GameResult {
+    pub fn white_player(&self) -> usize {
+        if self.white == 0 { self.players.0 } else { self.players.1 }
+    }
+
+    pub fn black_player(&self) -> usize {
+        if self.white == 0 { self.players.1 } else { self.players.0 }
+    }
+
+    pub fn winner_color(&self) -> Option<Color> {
+        self.winner.map(|w| if w == self.white { Color::White } else { Color::Black })
+    }
+}
+
+
+// Seed of the index-th game of a tournament, independent of how many games are played
+pub fn game_seed(master_seed: u64, index: usize) -> u64 {
+    let mixed = master_seed ^ (index as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    StdRng::seed_from_u64(mixed).gen()
+}
+
+
+// Games per Glicko-2 rating period
+const DEFAULT_RATING_PERIOD: usize = 100;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RatingSystem {
+    Elo,
+    Glicko2,
+}
+
+
+pub struct Tournament {
+    pub players: Vec<EloPlayer>,
+    threads: usize,
+    seed: u64,
+    // Both systems are always updated, this one decides the standings
+    rating_system: RatingSystem,
+    glicko: Glicko2,
+    // Games played so far, so that every game of the tournament gets its own seed
+    played: usize,
+    openings: Option<OpeningSource>,
+    next_opening: usize,
+    settings: GameSettings,
+}
+
+// A game to be played by a worker
+#[derive(Debug, Clone, Copy)]
+struct ScheduledGame {
+    pairing: (usize, usize),
+    seed: u64,
+    opening: Option<usize>,
+}
+
+impl Tournament {
+    pub fn new(players: Vec<EloPlayer>) -> Tournament {
+        let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        let glicko = Glicko2::new(players.len(), DEFAULT_RATING_PERIOD);
+        Tournament {
+            players,
+            threads,
+            seed: thread_rng().gen(),
+            rating_system: RatingSystem::Elo,
+            glicko,
+            played: 0,
+            openings: None,
+            next_opening: 0,
+            settings: GameSettings::new(),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    // From now on every pairing is played twice from the same opening, with colours reversed.
+    // Openings are used in order, one per pair of games.
+    pub fn set_openings(&mut self, openings: OpeningSuite) {
+        self.openings = Some(OpeningSource::Suite(openings));
+        self.next_opening = 0;
+    }
+
+    // Same as set_openings, with openings of random moves generated for every pair of games
+    pub fn set_random_openings(&mut self, plies: usize) {
+        self.openings = Some(OpeningSource::RandomPlies(plies));
+    }
+
+    pub fn openings(&self) -> Option<&OpeningSource> {
+        self.openings.as_ref()
+    }
+
+    pub fn settings(&self) -> &GameSettings {
+        &self.settings
+    }
+
+    pub fn set_adjudication(&mut self, adjudication: Adjudication) {
+        self.settings.adjudication = adjudication;
+    }
+
+    pub fn set_time_control(&mut self, time_control: Option<TimeControl>, tolerance: Duration) {
+        self.settings.time_control = time_control;
+        self.settings.time_tolerance = tolerance;
+    }
+
+    pub fn rating_system(&self) -> RatingSystem {
+        self.rating_system
+    }
+
+    pub fn set_rating_system(&mut self, rating_system: RatingSystem) {
+        self.rating_system = rating_system;
+    }
+
+    // Starts over with default Glicko-2 ratings, using periods of period_length games
+    pub fn set_rating_period(&mut self, period_length: usize) {
+        self.glicko = Glicko2::new(self.players.len(), period_length);
+    }
+
+    pub fn glicko(&self, player: usize) -> Glicko2Rating {
+        self.glicko.rating(player)
+    }
+
+    // Rating of a player in the selected system
+    pub fn rating(&self, player: usize) -> f64 {
+        match self.rating_system {
+            RatingSystem::Elo => self.players[player].elo_score(),
+            RatingSystem::Glicko2 => self.glicko.rating(player).rating,
+        }
+    }
+
+    // Player indexes, best first
+    pub fn standings(&self) -> Vec<usize> {
+        let mut order = (0..self.players.len()).collect::<Vec<_>>();
+        order.sort_by(|&a, &b| self.rating(b).total_cmp(&self.rating(a)));
+        order
+    }
+
+    // Plays rounds until the scheduler is done
+    pub fn run(&mut self, scheduler: &mut dyn Scheduler) -> Vec<GameResult> {
+        let mut results = Vec::new();
+        let mut last_round = Vec::new();
+        while let Some(pairings) = scheduler.next_round(&last_round) {
+            last_round = self.play(&pairings);
+            results.extend_from_slice(&last_round);
+        }
+        results
+    }
+
+    // Plays every pairing as (white, black), spreading games over the worker threads.
+    // With openings, every pairing is followed by the same game with colours reversed.
+    // Ratings are updated in pairing order once all games are done, so they don't depend on thread scheduling.
+    pub fn play(&mut self, pairings: &[(usize, usize)]) -> Vec<GameResult> {
+        let games = self.schedule(pairings);
+        let results = self.play_games(&games);
+        self.played += games.len();
+        for r in &results {
+            self.record(r);
+        }
+        // Don't leave games of an unfinished period out of the ratings
+        if self.glicko.has_pending_games() {
+            self.glicko.end_period();
+        }
+        results
+    }
+
+    fn schedule(&mut self, pairings: &[(usize, usize)]) -> Vec<ScheduledGame> {
+        let mut games = Vec::new();
+        for &(a, b) in pairings {
+            let seed = game_seed(self.seed, self.played + games.len());
+            match &self.openings {
+                Some(source) => {
+                    let opening = match source {
+                        OpeningSource::Suite(suite) => Some(self.next_opening % suite.len()),
+                        OpeningSource::RandomPlies(_) => None,
+                    };
+                    self.next_opening += 1;
+                    // Both games share the seed, so only the colours differ
+                    games.push(ScheduledGame { pairing: (a, b), seed, opening });
+                    games.push(ScheduledGame { pairing: (b, a), seed, opening });
+                }
+                None => games.push(ScheduledGame { pairing: (a, b), seed, opening: None }),
+            }
+        }
+        games
+    }
+
+    fn play_games(&self, games: &[ScheduledGame]) -> Vec<GameResult> {
+        let next = AtomicUsize::new(0);
+        let players = &self.players;
+        let openings = self.openings.as_ref();
+        let settings = &self.settings;
+        let progress = ProgressBar::new(games.len() as u64);
+
+        let mut results = vec![None; games.len()];
+        thread::scope(|s| {
+            let (sender, receiver) = mpsc::channel();
+            for _ in 0..cmp::min(self.threads, games.len()) {
+                let sender = sender.clone();
+                let next = &next;
+                s.spawn(move || {
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        if index >= games.len() {
+                            break;
+                        }
+
+                        let result = play_game(players, &games[index], openings, settings);
+                        if sender.send((index, result)).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(sender);
+
+            for (index, result) in receiver {
+                results[index] = Some(result);
+                progress.inc(1);
+            }
+        });
+        progress.finish();
+
+        results.into_iter().map(|r| r.expect("Game was not played")).collect()
+    }
+
+    // Plays a game again, with the same players, seed and opening
+    pub fn replay(&self, result: &GameResult) -> GameResult {
+        let game = ScheduledGame {
+            pairing: result.players,
+            seed: result.seed,
+            opening: result.opening,
+        };
+        play_game(&self.players, &game, self.openings.as_ref(), &self.settings)
+    }
+
+    fn record(&mut self, result: &GameResult) {
+        let (a, b) = result.players;
+        assert!(a != b);
+
+        let (first, second) = (cmp::min(a, b), cmp::max(a, b));
+        let (pa, pb) = self.players.split_at_mut(second);
+        let (pa, pb) = (&mut pa[first], &mut pb[0]);
+
+        let winner = if a == first {
+            result.winner
+        } else {
+            result.winner.map(|w| 1 - w)
+        };
+        pa.record(pb, winner);
+
+        let score = match winner {
+            Some(0) => 1.0,
+            Some(_) => 0.0,
+            None => 0.5,
+        };
+        self.glicko.add_game(first, second, score);
+    }
+}
+
+// Controllers are created for every game, so the outcome only depends on the seed and not on games played before
+fn play_game(players: &[EloPlayer], game: &ScheduledGame, openings: Option<&OpeningSource>, settings: &GameSettings) -> GameResult {
+    let (a, b) = game.pairing;
+    let controllers: [Box<dyn PlayerController>; 2] = [players[a].controller(), players[b].controller()];
+    let controllers = [&*controllers[0], &*controllers[1]];
+    let start = Instant::now();
+    let outcome = match (openings, game.opening) {
+        (Some(OpeningSource::Suite(suite)), Some(i)) => play_opening(controllers, settings, game.seed, 0, suite.get(i)),
+        // Both games of a pair share the seed, so they start from the same random opening
+        (Some(OpeningSource::RandomPlies(plies)), _) => play_opening(controllers, settings, game.seed, 0, &random_opening(*plies, game.seed)),
+        _ => play_once_with_white(controllers, settings, game.seed, 0),
+    };
+    GameResult {
+        players: game.pairing,
+        winner: outcome.winner,
+        white: outcome.white,
+        moves: outcome.moves,
+        seed: game.seed,
+        termination: outcome.termination,
+        duration: start.elapsed(),
+        opening: game.opening,
+    }
+}
+
+
+
+#[test]
+fn tournament_results_follow_pairings() {
+    use crate::ai::*;
+
+    let players = vec![
+        EloPlayer::new(FirstMoveAI::new),
+        EloPlayer::new(SwarmAI::new),
+        EloPlayer::new(CaptureAI::new),
+    ];
+    let pairings = vec![(0, 1), (1, 2), (2, 0), (1, 0), (0, 2)];
+
+    let mut tournament = Tournament::new(players);
+    tournament.set_threads(3);
+    let results = tournament.play(&pairings);
+
+    assert_eq!(results.len(), pairings.len());
+    for (r, p) in results.iter().zip(&pairings) {
+        assert_eq!(r.players, *p);
+    }
+    let games = tournament.players.iter().map(|p| p.victories + p.loses + p.draws).sum::<usize>();
+    assert_eq!(games, pairings.len() * 2);
+}
+
+#[test]
+fn tournament_is_reproducible() {
+    use crate::ai::*;
+
+    let new_tournament = || {
+        let mut t = Tournament::new(vec![
+            EloPlayer::new(|| RandomAI::new(false)),
+            EloPlayer::new(|| CaptureAI::new_with_fallback(RandomAI::new(false))),
+        ]);
+        t.set_seed(1234);
+        t.set_threads(2);
+        t
+    };
+    let pairings = vec![(0, 1), (1, 0), (0, 1), (1, 0)];
+
+    let mut a = new_tournament();
+    let mut b = new_tournament();
+    let ra = a.play(&pairings);
+    let rb = b.play(&pairings);
+    for (x, y) in ra.iter().zip(&rb) {
+        assert_eq!((x.winner, x.moves, x.seed), (y.winner, y.moves, y.seed));
+
+        let replayed = a.replay(x);
+        assert_eq!((x.winner, x.moves), (replayed.winner, replayed.moves));
+    }
+    assert_eq!(a.players[0].elo_score(), b.players[0].elo_score());
+    assert_eq!(a.glicko(0), b.glicko(0));
+}
+
+#[test]
+fn tournament_openings_are_played_twice() {
+    use crate::ai::*;
+
+    let suite = OpeningSuite::new(parse_epd("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b\n8/8/8/4k3/8/8/3QK3/8 w\n").unwrap());
+    let mut tournament = Tournament::new(vec![EloPlayer::new(FirstMoveAI::new), EloPlayer::new(SwarmAI::new)]);
+    tournament.set_openings(suite);
+    let results = tournament.play(&[(0, 1), (1, 0), (0, 1)]);
+
+    assert_eq!(results.len(), 6);
+    for pair in results.chunks(2) {
+        assert_eq!(pair[0].players, (pair[1].players.1, pair[1].players.0));
+        assert_eq!((pair[0].seed, pair[0].opening), (pair[1].seed, pair[1].opening));
+    }
+    assert_eq!(results.iter().map(|r| r.opening.unwrap()).collect::<Vec<_>>(), vec![0, 0, 1, 1, 0, 0]);
+
+    let replayed = tournament.replay(&results[2]);
+    assert_eq!((replayed.winner, replayed.moves), (results[2].winner, results[2].moves));
+}
+
+#[test]
+fn tournament_random_openings_are_shared_by_pairs() {
+    use crate::ai::*;
+
+    assert_eq!(random_opening(8, 42).fen(), random_opening(8, 42).fen());
+    assert_ne!(random_opening(8, 42).fen(), random_opening(8, 43).fen());
+    assert_eq!(random_opening(3, 1).to_move, Color::Black);
+
+    let mut tournament = Tournament::new(vec![EloPlayer::new(FirstMoveAI::new), EloPlayer::new(SwarmAI::new)]);
+    tournament.set_seed(1);
+    tournament.set_random_openings(6);
+    let results = tournament.play(&[(0, 1), (0, 1), (0, 1), (0, 1)]);
+
+    assert_eq!(results.len(), 8);
+    for pair in results.chunks(2) {
+        assert_eq!(pair[0].seed, pair[1].seed);
+        assert_eq!(pair[0].white_player(), pair[1].black_player());
+    }
+    // Deterministic players only differ through their openings
+    let moves = results.iter().step_by(2).map(|r| r.moves).collect::<Vec<_>>();
+    assert!(moves.iter().any(|&m| m != moves[0]));
+}
@@ -0,0 +1,123 @@
+use crate::pos::*;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+const DEFAULT_TABLE_SIZE: usize = 1 << 16;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    // Score is at least this value (fail high)
+    Lower,
+    // Score is at most this value (fail low)
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TtEntry {
+    pub depth: u8,
+    pub score: i64,
+    pub bound: Bound,
+    pub mv: Option<Move>,
+}
+
+impl TtEntry {
+    fn pack(&self) -> u64 {
+        let bound = match self.bound {
+            Bound::Exact => 0,
+            Bound::Lower => 1,
+            Bound::Upper => 2,
+        };
+        let mv = match self.mv {
+            Some(Move(a, b)) => 1 | ((a.index() as u64) << 1) | ((b.index() as u64) << 7),
+            None => 0,
+        };
+        (self.score as i32 as u32 as u64) | ((self.depth as u64) << 32) | (bound << 40) | (mv << 42)
+    }
+
+    fn unpack(data: u64) -> TtEntry {
+        let mv = data >> 42;
+        TtEntry {
+            score: data as u32 as i32 as i64,
+            depth: (data >> 32) as u8,
+            bound: match (data >> 40) & 3 {
+                0 => Bound::Exact,
+                1 => Bound::Lower,
+                _ => Bound::Upper,
+            },
+            mv: if mv & 1 == 1 {
+                Some(Move(Pos::from_index(((mv >> 1) & 63) as usize), Pos::from_index(((mv >> 7) & 63) as usize)))
+            } else {
+                None
+            },
+        }
+    }
+}
+
+
+// Lock-free table shared between search threads.
+// Each slot stores key ^ data next to data, so torn writes from concurrent stores are detected on probe and ignored.
+pub struct TranspositionTable {
+    slots: Vec<(AtomicU64, AtomicU64)>,
+}
+
+impl TranspositionTable {
+    pub fn new() -> TranspositionTable {
+        TranspositionTable::with_size(DEFAULT_TABLE_SIZE)
+    }
+
+    pub fn with_size(size: usize) -> TranspositionTable {
+        TranspositionTable {
+            slots: (0..size.next_power_of_two()).map(|_| (AtomicU64::new(0), AtomicU64::new(0))).collect(),
+        }
+    }
+
+    fn slot(&self, key: u64) -> &(AtomicU64, AtomicU64) {
+        &self.slots[(key as usize) & (self.slots.len() - 1)]
+    }
+
+    pub fn probe(&self, key: u64) -> Option<TtEntry> {
+        let slot = self.slot(key);
+        let check = slot.0.load(Ordering::Relaxed);
+        let data = slot.1.load(Ordering::Relaxed);
+        if data != 0 && check ^ data == key {
+            Some(TtEntry::unpack(data))
+        } else {
+            None
+        }
+    }
+
+    pub fn store(&self, key: u64, entry: TtEntry) {
+        let slot = self.slot(key);
+        let data = entry.pack();
+        slot.0.store(key ^ data, Ordering::Relaxed);
+        slot.1.store(data, Ordering::Relaxed);
+    }
+
+    pub fn clear(&self) {
+        for slot in &self.slots {
+            slot.0.store(0, Ordering::Relaxed);
+            slot.1.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+
+
+#[test]
+fn tt_store_and_probe() {
+    let tt = TranspositionTable::with_size(16);
+    let entry = TtEntry {
+        depth: 7,
+        score: -99_950,
+        bound: Bound::Lower,
+        mv: Some(Move(Pos::new(4, 1), Pos::new(4, 3))),
+    };
+
+    assert_eq!(tt.probe(12345), None);
+    tt.store(12345, entry);
+    assert_eq!(tt.probe(12345), Some(entry));
+    // Same slot, different key
+    assert_eq!(tt.probe(12345 + 16), None);
+}
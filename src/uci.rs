@@ -0,0 +1,371 @@
+use crate::board::*;
+use crate::piece::*;
+use crate::pos::*;
+use crate::player::*;
+use crate::clock::*;
+use crate::search::*;
+use crate::opening::*;
+
+use std::cell::RefCell;
+use std::ffi::{OsStr, OsString};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Runs an external engine speaking UCI as a controller.
+// Positions are sent as FEN: without castling, en passant or promotion the FEN is the whole position, only the repetition history is lost.
+// A crashed or unresponsive engine resigns the game and is restarted for the next one.
+
+// Time given on top of what the engine was told it could use, and to answer uci and isready
+const DEFAULT_UCI_TIMEOUT: Duration = Duration::from_secs(5);
+// After a timeout, time left to answer stop before the engine is killed
+const STOP_GRACE: Duration = Duration::from_millis(500);
+const QUIT_GRACE: Duration = Duration::from_millis(100);
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UciLimit {
+    Depth(usize),
+    Nodes(u64),
+    MoveTime(Duration),
+}
+
+impl UciLimit {
+    fn go(&self) -> String {
+        match *self {
+            UciLimit::Depth(depth) => format!("go depth {}", depth),
+            UciLimit::Nodes(nodes) => format!("go nodes {}", nodes),
+            UciLimit::MoveTime(time) => format!("go movetime {}", time.as_millis()),
+        }
+    }
+
+    fn time(&self) -> Duration {
+        match *self {
+            UciLimit::MoveTime(time) => time,
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+
+// A running engine process, its output is read by a thread so it can be waited on with a timeout
+struct Engine {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+}
+
+impl Engine {
+    fn spawn(command: &Path, args: &[OsString]) -> io::Result<Engine> {
+        let mut child = Command::new(command)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let stdin = child.stdin.take().expect("Engine stdin is piped");
+        let stdout = child.stdout.take().expect("Engine stdout is piped");
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            // Ends when the engine exits, or when the engine is dropped and nobody listens anymore
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Engine {
+            child,
+            stdin,
+            lines,
+        })
+    }
+
+    fn send(&mut self, command: &str) -> io::Result<()> {
+        writeln!(self.stdin, "{}", command)?;
+        self.stdin.flush()
+    }
+
+    // Reads lines until on_line returns something or the deadline passes
+    fn read_until<T, F: FnMut(&str) -> Option<T>>(&mut self, deadline: Instant, mut on_line: F) -> io::Result<T> {
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match self.lines.recv_timeout(timeout) {
+                Ok(line) => {
+                    if let Some(result) = on_line(line.trim()) {
+                        return Ok(result);
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => return Err(io::Error::new(io::ErrorKind::TimedOut, "Engine did not answer in time")),
+                Err(RecvTimeoutError::Disconnected) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Engine exited")),
+            }
+        }
+    }
+
+    // Returns the engine's name
+    fn handshake(&mut self, timeout: Duration) -> io::Result<String> {
+        self.send("uci")?;
+        let mut name = None;
+        self.read_until(Instant::now() + timeout, |line| {
+            if let Some(n) = line.strip_prefix("id name ") {
+                name = Some(n.trim().to_string());
+            }
+            (line == "uciok").then_some(())
+        })?;
+        Ok(name.unwrap_or_else(|| "UciEngine".to_string()))
+    }
+
+    fn ready(&mut self, timeout: Duration) -> io::Result<()> {
+        self.send("isready")?;
+        self.read_until(Instant::now() + timeout, |line| (line == "readyok").then_some(()))
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let deadline = Instant::now() + QUIT_GRACE;
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+
+// Every controller runs its own engine process. Tournaments build one controller per game, so each game
+// starts a process and goes through the uci handshake, and one more is started when the player is created to learn its name.
+pub struct UciEngineController {
+    command: OsString,
+    args: Vec<OsString>,
+    name: String,
+    limit: UciLimit,
+    timeout: Duration,
+    log: bool,
+    engine: RefCell<Option<Engine>>,
+    last_info: RefCell<Option<SearchInfo>>,
+    last_error: RefCell<Option<String>>,
+}
+
+impl UciEngineController {
+    pub fn new<P: AsRef<Path>>(command: P) -> io::Result<UciEngineController> {
+        UciEngineController::new_with_args(command, &[] as &[&str])
+    }
+
+    // Starts the engine right away to learn its name
+    pub fn new_with_args<P: AsRef<Path>, S: AsRef<OsStr>>(command: P, args: &[S]) -> io::Result<UciEngineController> {
+        let command = command.as_ref();
+        let args = args.iter().map(|a| a.as_ref().to_os_string()).collect::<Vec<_>>();
+        let mut engine = Engine::spawn(command, &args)?;
+        let name = engine.handshake(DEFAULT_UCI_TIMEOUT)?;
+
+        Ok(UciEngineController {
+            command: command.as_os_str().to_os_string(),
+            args,
+            name,
+            limit: UciLimit::Depth(4),
+            timeout: DEFAULT_UCI_TIMEOUT,
+            log: false,
+            engine: RefCell::new(Some(engine)),
+            last_info: RefCell::new(None),
+            last_error: RefCell::new(None),
+        })
+    }
+
+    // Used when the game has no clock
+    pub fn set_limit(&mut self, limit: UciLimit) {
+        self.limit = limit;
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    // Also prints engine errors as they happen
+    pub fn set_log(&mut self, log: bool) {
+        self.log = log;
+    }
+
+    // Last search info the engine sent
+    pub fn last_info(&self) -> Option<SearchInfo> {
+        self.last_info.borrow().clone()
+    }
+
+    // Why the engine last failed to answer, it resigned the game then
+    pub fn last_error(&self) -> Option<String> {
+        self.last_error.borrow().clone()
+    }
+
+    fn report(&self, error: String) {
+        if self.log {
+            println!("{}: {}", self.name, error);
+        }
+        *self.last_error.borrow_mut() = Some(error);
+    }
+
+    // Restarts the engine if it isn't running
+    fn with_engine<T, F: FnOnce(&mut Engine) -> io::Result<T>>(&self, f: F) -> io::Result<T> {
+        let mut engine = self.engine.borrow_mut();
+        if engine.is_none() {
+            let mut restarted = Engine::spawn(Path::new(&self.command), &self.args)?;
+            restarted.handshake(self.timeout)?;
+            *engine = Some(restarted);
+        }
+
+        let result = f(engine.as_mut().unwrap());
+        if result.is_err() {
+            // Dropping it kills it, the next call starts a new one
+            *engine = None;
+        }
+        result
+    }
+
+    fn think(&self, color: Color, board: &Board, go: &str, allowed: Duration) -> Action {
+        let log = self.log;
+        let mut last_info = None;
+        let result = self.with_engine(|engine| {
+            engine.send(&format!("position fen {}", to_fen(board, color)))?;
+            engine.send(go)?;
+
+            let mut on_line = |line: &str| {
+                if let Some(info) = parse_info(line) {
+                    if log {
+                        println!("{}", info);
+                    }
+                    last_info = Some(info);
+                }
+                line.strip_prefix("bestmove").map(|m| m.split_whitespace().next().unwrap_or("").to_string())
+            };
+
+            match engine.read_until(Instant::now() + allowed + self.timeout, &mut on_line) {
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                    engine.send("stop")?;
+                    engine.read_until(Instant::now() + STOP_GRACE, &mut on_line)
+                }
+                result => result,
+            }
+        });
+        *self.last_info.borrow_mut() = last_info;
+
+        match result {
+            Ok(m) => match Move::from_str(&m) {
+                Ok(m) => Action::Move(m),
+                // (none) or 0000 when the engine has no move
+                Err(_) => {
+                    self.report(format!("no usable move \"{}\" ({})", m, color));
+                    Action::Resign
+                }
+            },
+            Err(e) => {
+                self.report(format!("{} ({})", e, color));
+                Action::Resign
+            }
+        }
+    }
+}
+
+impl PlayerController for UciEngineController {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn play(&self, color: Color, board: &Board) -> Action {
+        self.think(color, board, &self.limit.go(), self.limit.time())
+    }
+
+    fn play_with_clock(&self, color: Color, board: &Board, clock: &ClockInfo) -> Action {
+        if clock.per_move {
+            return self.think(color, board, &format!("go movetime {}", clock.remaining.as_millis()), clock.remaining);
+        }
+
+        let (mut white, mut black) = (clock.remaining, clock.opponent_remaining);
+        if color == Color::Black {
+            std::mem::swap(&mut white, &mut black);
+        }
+        let increment = clock.increment.as_millis();
+        let mut go = format!("go wtime {} btime {} winc {} binc {}", white.as_millis(), black.as_millis(), increment, increment);
+        if let Some(moves) = clock.moves_to_go {
+            go.push_str(&format!(" movestogo {}", moves));
+        }
+        self.think(color, board, &go, clock.remaining)
+    }
+
+    fn new_game(&self, _color: Color, _board: &Board) {
+        let timeout = self.timeout;
+        let ready = self.with_engine(|engine| {
+            engine.send("ucinewgame")?;
+            engine.ready(timeout)
+        });
+        if let Err(e) = ready {
+            self.report(e.to_string());
+        }
+    }
+}
+
+
+// Reads an info line into the same form as our own search reports, None for lines without a depth such as info string
+pub fn parse_info(line: &str) -> Option<SearchInfo> {
+    let mut tokens = line.split_whitespace();
+    if tokens.next() != Some("info") {
+        return None;
+    }
+
+    let mut depth = None;
+    let mut score = 0;
+    let mut nodes = 0;
+    let mut time = Duration::ZERO;
+    let mut pv = Vec::new();
+    while let Some(token) = tokens.next() {
+        match token {
+            "depth" => depth = tokens.next().and_then(|t| t.parse().ok()),
+            "nodes" => nodes = tokens.next().and_then(|t| t.parse().ok()).unwrap_or(0),
+            "time" => time = Duration::from_millis(tokens.next().and_then(|t| t.parse().ok()).unwrap_or(0)),
+            "score" => match (tokens.next(), tokens.next().and_then(|t| t.parse::<i64>().ok())) {
+                (Some("cp"), Some(cp)) => score = cp,
+                // Mate in n moves is 2n - 1 plies, getting mated in n is 2n
+                (Some("mate"), Some(n)) if n > 0 => score = MATE_SCORE - (2 * n - 1),
+                (Some("mate"), Some(n)) => score = -MATE_SCORE + 2 * -n,
+                _ => {}
+            },
+            // Always last
+            "pv" => {
+                pv = tokens.by_ref().map_while(|m| Move::from_str(m).ok()).collect();
+            }
+            // Anything else is skipped, along with its value
+            "string" => return None,
+            _ => {}
+        }
+    }
+
+    depth.map(|depth| SearchInfo {
+        depth,
+        score,
+        nodes,
+        time,
+        pv,
+    })
+}
+
+
+
+#[test]
+fn uci_info_parsing() {
+    let info = parse_info("info depth 7 seldepth 9 score mate -2 nodes 1234 nps 1000 time 56 pv e2e4 e7e5").unwrap();
+    assert_eq!((info.depth, info.nodes, info.time), (7, 1234, Duration::from_millis(56)));
+    assert_eq!(info.mate_in(), Some(-2));
+    assert_eq!(info.pv, [Move::from_str("e2e4").unwrap(), Move::from_str("e7e5").unwrap()]);
+    assert_eq!(parse_info("info depth 1 score mate 3").unwrap().mate_in(), Some(3));
+    assert_eq!(parse_info("info depth 2 score cp -35").unwrap().score, -35);
+    assert!(parse_info("info string depth 3").is_none());
+    assert!(parse_info("bestmove e2e4").is_none());
+}
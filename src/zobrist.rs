@@ -0,0 +1,63 @@
+use crate::piece::*;
+use crate::pos::*;
+
+const PIECE_KINDS: usize = 7;
+
+// Keys are generated at compile time so hashes are stable between runs
+const KEYS: [[[u64; 64]; PIECE_KINDS]; 2] = generate_keys(0x9E37_79B9_7F4A_7C15);
+
+pub const SIDE_KEY: u64 = splitmix64(0xD1B5_4A32_D192_ED03).1;
+
+
+const fn splitmix64(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    (state, z ^ (z >> 31))
+}
+
+const fn generate_keys(seed: u64) -> [[[u64; 64]; PIECE_KINDS]; 2] {
+    let mut keys = [[[0; 64]; PIECE_KINDS]; 2];
+    let mut state = seed;
+    let mut color = 0;
+    while color < 2 {
+        // Empty squares keep a null key
+        let mut piece = 1;
+        while piece < PIECE_KINDS {
+            let mut square = 0;
+            while square < 64 {
+                let (next, key) = splitmix64(state);
+                state = next;
+                keys[color][piece][square] = key;
+                square += 1;
+            }
+            piece += 1;
+        }
+        color += 1;
+    }
+    keys
+}
+
+fn piece_index(piece: Piece) -> usize {
+    match piece {
+        Piece::Empty => 0,
+        Piece::Pawn => 1,
+        Piece::Rook => 2,
+        Piece::Knight => 3,
+        Piece::Bishop => 4,
+        Piece::Queen => 5,
+        Piece::King => 6,
+    }
+}
+
+pub fn piece_key(piece: ColoredPiece, pos: Pos) -> u64 {
+    KEYS[piece.color.index()][piece_index(piece.piece)][pos.index()]
+}
+
+pub fn side_key(color: Color) -> u64 {
+    match color {
+        Color::White => 0,
+        Color::Black => SIDE_KEY,
+    }
+}
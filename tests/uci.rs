@@ -0,0 +1,49 @@
+// Runs the UCI adapter against the scripted engine of src/bin/uci_stub.rs
+
+extern crate chess_rs;
+
+use chess_rs::ai::*;
+use chess_rs::board::*;
+use chess_rs::elo::*;
+use chess_rs::piece::*;
+use chess_rs::player::*;
+use chess_rs::pos::*;
+use chess_rs::uci::*;
+
+use std::str::FromStr;
+use std::time::Duration;
+
+#[test]
+fn uci_engine_stub() {
+    // Built by cargo for integration tests
+    let stub = env!("CARGO_BIN_EXE_uci_stub");
+
+    let engine = UciEngineController::new_with_args(stub, &["--name", "Knight", "g1f3", "f3g1"]).unwrap();
+    assert_eq!(engine.name(), "Knight");
+    let board = Board::new();
+    engine.new_game(Color::White, &board);
+    assert_eq!(engine.play(Color::White, &board), Action::Move(Move::from_str("g1f3").unwrap()));
+    assert_eq!(engine.last_info().unwrap().pv, [Move::from_str("g1f3").unwrap()]);
+
+    // Shuffles its knight until the move limit, the script starts over with the process
+    let engine = UciEngineController::new_with_args(stub, &["g1f3", "f3g1"]).unwrap();
+    let settings = GameSettings { max_moves: 8, ..GameSettings::new() };
+    let outcome = play_once_with_white([&engine, &FirstMoveAI::new()], &settings, 1, 0);
+    assert_eq!(outcome.termination, Termination::MoveLimit);
+
+    let mut hanging = UciEngineController::new_with_args(stub, &["--hang"]).unwrap();
+    hanging.set_timeout(Duration::from_millis(50));
+    assert_eq!(hanging.play(Color::White, &board), Action::Resign);
+    assert!(hanging.last_error().is_some());
+
+    // Crashes on its second go, then a new process is started
+    let crashing = UciEngineController::new_with_args(stub, &["--crash-after", "2", "e2e4"]).unwrap();
+    let e2e4 = Action::Move(Move::from_str("e2e4").unwrap());
+    assert_eq!(crashing.play(Color::White, &board), e2e4);
+    assert_eq!(crashing.play(Color::White, &board), Action::Resign);
+    assert_eq!(crashing.play(Color::White, &board), e2e4);
+
+    let out_of_moves = UciEngineController::new(stub).unwrap();
+    assert_eq!(out_of_moves.play(Color::White, &board), Action::Resign);
+    assert_eq!(out_of_moves.last_error(), Some("no usable move \"0000\" (White)".to_string()));
+}